@@ -1,4 +1,5 @@
 import {
+    AccountMeta,
     Keypair,
     Connection,
     PublicKey,
@@ -11,7 +12,9 @@ import {
 import fs from 'mz/fs';
 import path from 'path';
 import * as borsh from 'borsh';
+import BN from 'bn.js';
 import { Buffer } from 'buffer';
+import { createHash } from 'crypto';
 import { getPayer, getRpcUrl, createKeypairFromFile } from './utils';
 
 /**
@@ -53,35 +56,873 @@ const PROGRAM_SO_PATH = path.join(PROGRAM_PATH, 'gm_program.so');
  */
 const PROGRAM_KEYPAIR_PATH = path.join(PROGRAM_PATH, 'gm_program-keypair.json');
 
-const NAME_FOR_GM='Glass Chewer'
+// The program only accepts names in Unicode normalization form C
+const NAME_FOR_GM='Glass Chewer'.normalize('NFC')
 
 /**
- * Borsh class and schema definition for greeting accounts
+ * Borsh classes and schema definition for greeting accounts
  */
 
+class HistoryEntry {
+    greeter = new Uint8Array(32);
+    name_hash = new Uint8Array(32);
+    unix_timestamp = new BN(0);
+    constructor(fields: {
+        greeter: Uint8Array,
+        name_hash: Uint8Array,
+        unix_timestamp: BN,
+    } | undefined = undefined) {
+      if (fields) {
+        this.greeter = fields.greeter;
+        this.name_hash = fields.name_hash;
+        this.unix_timestamp = fields.unix_timestamp;
+      }
+    }
+}
+
+class GreetingHistory {
+    capacity = 0;
+    head = 0;
+    entries: HistoryEntry[] = [];
+    constructor(fields: {
+        capacity: number,
+        head: number,
+        entries: HistoryEntry[],
+    } | undefined = undefined) {
+      if (fields) {
+        this.capacity = fields.capacity;
+        this.head = fields.head;
+        this.entries = fields.entries;
+      }
+    }
+
+    /**
+     * The recorded GMs, most recent first
+     */
+    recent(): HistoryEntry[] {
+        // Once the buffer is full the oldest entries start at the head
+        return this.entries
+            .slice(this.head)
+            .concat(this.entries.slice(0, this.head))
+            .reverse();
+    }
+}
+
 class GmAccount {
+    authority = new Uint8Array(32);
+    created_at = new BN(0);
+    last_gm_unix_timestamp = new BN(0);
+    last_gm_slot = new BN(0);
+    gm_count = new BN(0);
+    distinct_greeters = new BN(0);
+    first_greeter: Uint8Array | undefined = undefined;
     name = "";
-    constructor(fields: {name: string} | undefined = undefined) {
+    history = new GreetingHistory();
+    constructor(fields: {
+        authority: Uint8Array,
+        created_at: BN,
+        last_gm_unix_timestamp: BN,
+        last_gm_slot: BN,
+        gm_count: BN,
+        distinct_greeters: BN,
+        first_greeter: Uint8Array | undefined,
+        name: string,
+        history: GreetingHistory,
+    } | undefined = undefined) {
       if (fields) {
+        this.authority = fields.authority;
+        this.created_at = fields.created_at;
+        this.last_gm_unix_timestamp = fields.last_gm_unix_timestamp;
+        this.last_gm_slot = fields.last_gm_slot;
+        this.gm_count = fields.gm_count;
+        this.distinct_greeters = fields.distinct_greeters;
+        this.first_greeter = fields.first_greeter;
         this.name = fields.name;
+        this.history = fields.history;
       }
     }
-    static schema = new Map([[GmAccount,
+    // Timestamps are i64 on chain, but never negative so they decode as u64
+    static schema = new Map<Function, any>([
+        [GmAccount,
+        {
+            kind: 'struct',
+            fields: [
+                ['authority', [32]],
+                ['created_at', 'u64'],
+                ['last_gm_unix_timestamp', 'u64'],
+                ['last_gm_slot', 'u64'],
+                ['gm_count', 'u64'],
+                ['distinct_greeters', 'u64'],
+                ['first_greeter', { kind: 'option', type: [32] }],
+                ['name', 'string'],
+                ['history', GreetingHistory]]
+        }],
+        [GreetingHistory,
+        {
+            kind: 'struct',
+            fields: [
+                ['capacity', 'u8'],
+                ['head', 'u8'],
+                ['entries', [HistoryEntry]]]
+        }],
+        [HistoryEntry,
         {
             kind: 'struct',
             fields: [
-                ['name', 'string']]
+                ['greeter', [32]],
+                ['name_hash', [32]],
+                ['unix_timestamp', 'u64']]
         }]]);
 }
 
 
 /**
- * The expected size of each greeting account. Used for creating the buffer
+ * Borsh classes and schema definition for inbox accounts
+ */
+
+class InboxHeader {
+    recipient = new Uint8Array(32);
+    gm_count = new BN(0);
+    page_count = 0;
+    constructor(fields: {
+        recipient: Uint8Array,
+        gm_count: BN,
+        page_count: number,
+    } | undefined = undefined) {
+      if (fields) {
+        this.recipient = fields.recipient;
+        this.gm_count = fields.gm_count;
+        this.page_count = fields.page_count;
+      }
+    }
+}
+
+class InboxEntry {
+    sender = new Uint8Array(32);
+    unix_timestamp = new BN(0);
+    slot = new BN(0);
+    constructor(fields: {
+        sender: Uint8Array,
+        unix_timestamp: BN,
+        slot: BN,
+    } | undefined = undefined) {
+      if (fields) {
+        this.sender = fields.sender;
+        this.unix_timestamp = fields.unix_timestamp;
+        this.slot = fields.slot;
+      }
+    }
+}
+
+class InboxPage {
+    recipient = new Uint8Array(32);
+    index = 0;
+    entries: InboxEntry[] = [];
+    constructor(fields: {
+        recipient: Uint8Array,
+        index: number,
+        entries: InboxEntry[],
+    } | undefined = undefined) {
+      if (fields) {
+        this.recipient = fields.recipient;
+        this.index = fields.index;
+        this.entries = fields.entries;
+      }
+    }
+}
+
+const INBOX_SCHEMA = new Map<Function, any>([
+    [InboxHeader,
+    {
+        kind: 'struct',
+        fields: [
+            ['recipient', [32]],
+            ['gm_count', 'u64'],
+            ['page_count', 'u32']]
+    }],
+    [InboxPage,
+    {
+        kind: 'struct',
+        fields: [
+            ['recipient', [32]],
+            ['index', 'u32'],
+            ['entries', [InboxEntry]]]
+    }],
+    [InboxEntry,
+    {
+        kind: 'struct',
+        fields: [
+            ['sender', [32]],
+            ['unix_timestamp', 'u64'],
+            ['slot', 'u64']]
+    }]]);
+
+/**
+ * Borsh classes and schema definition for outbox accounts
+ */
+
+class OutboxEntry {
+    recipient = new Uint8Array(32);
+    unix_timestamp = new BN(0);
+    constructor(fields: {
+        recipient: Uint8Array,
+        unix_timestamp: BN,
+    } | undefined = undefined) {
+      if (fields) {
+        this.recipient = fields.recipient;
+        this.unix_timestamp = fields.unix_timestamp;
+      }
+    }
+}
+
+class TokenBucket {
+    tokens = new BN(0);
+    last_refill_slot = new BN(0);
+    constructor(fields: {
+        tokens: BN,
+        last_refill_slot: BN,
+    } | undefined = undefined) {
+      if (fields) {
+        this.tokens = fields.tokens;
+        this.last_refill_slot = fields.last_refill_slot;
+      }
+    }
+}
+
+class Outbox {
+    sender = new Uint8Array(32);
+    gm_count = new BN(0);
+    quota = new TokenBucket();
+    page_count = 0;
+    constructor(fields: {
+        sender: Uint8Array,
+        gm_count: BN,
+        quota: TokenBucket,
+        page_count: number,
+    } | undefined = undefined) {
+      if (fields) {
+        this.sender = fields.sender;
+        this.gm_count = fields.gm_count;
+        this.quota = fields.quota;
+        this.page_count = fields.page_count;
+      }
+    }
+}
+
+class OutboxPage {
+    sender = new Uint8Array(32);
+    index = 0;
+    entries: OutboxEntry[] = [];
+    constructor(fields: {
+        sender: Uint8Array,
+        index: number,
+        entries: OutboxEntry[],
+    } | undefined = undefined) {
+      if (fields) {
+        this.sender = fields.sender;
+        this.index = fields.index;
+        this.entries = fields.entries;
+      }
+    }
+}
+
+const OUTBOX_SCHEMA = new Map<Function, any>([
+    [Outbox,
+    {
+        kind: 'struct',
+        fields: [
+            ['sender', [32]],
+            ['gm_count', 'u64'],
+            ['quota', TokenBucket],
+            ['page_count', 'u32']]
+    }],
+    [TokenBucket,
+    {
+        kind: 'struct',
+        fields: [
+            ['tokens', 'u64'],
+            ['last_refill_slot', 'u64']]
+    }],
+    [OutboxPage,
+    {
+        kind: 'struct',
+        fields: [
+            ['sender', [32]],
+            ['index', 'u32'],
+            ['entries', [OutboxEntry]]]
+    }],
+    [OutboxEntry,
+    {
+        kind: 'struct',
+        fields: [
+            ['recipient', [32]],
+            ['unix_timestamp', 'u64']]
+    }]]);
+
+/**
+ * Borsh classes and schema definition for profile accounts
+ */
+
+class GmStreak {
+    current = 0;
+    longest = 0;
+    last_day = new BN(0);
+    constructor(fields: {
+        current: number,
+        longest: number,
+        last_day: BN,
+    } | undefined = undefined) {
+      if (fields) {
+        this.current = fields.current;
+        this.longest = fields.longest;
+        this.last_day = fields.last_day;
+      }
+    }
+}
+
+class Avatar {
+    uri = '';
+    content_hash = new Uint8Array(32);
+    constructor(fields: {
+        uri: string,
+        content_hash: Uint8Array,
+    } | undefined = undefined) {
+      if (fields) {
+        this.uri = fields.uri;
+        this.content_hash = fields.content_hash;
+      }
+    }
+}
+
+class Profile {
+    wallet = new Uint8Array(32);
+    // Read as a u16 since borsh-js has no signed integers, see `utcOffsetMinutes`
+    utc_offset = 0;
+    streak = new GmStreak();
+    display_name = '';
+    avatar: Avatar | null = null;
+    bio = '';
+    constructor(fields: {
+        wallet: Uint8Array,
+        utc_offset: number,
+        streak: GmStreak,
+        display_name: string,
+        avatar: Avatar | null,
+        bio: string,
+    } | undefined = undefined) {
+      if (fields) {
+        this.wallet = fields.wallet;
+        this.utc_offset = fields.utc_offset;
+        this.streak = fields.streak;
+        this.display_name = fields.display_name;
+        this.avatar = fields.avatar;
+        this.bio = fields.bio;
+      }
+    }
+
+    /**
+     * Offset of the wallet's time zone from UTC, in minutes
+     */
+    utcOffsetMinutes(): number {
+        return this.utc_offset >= 0x8000 ? this.utc_offset - 0x10000 : this.utc_offset;
+    }
+}
+
+const PROFILE_SCHEMA = new Map<Function, any>([
+    [Profile,
+    {
+        kind: 'struct',
+        fields: [
+            ['wallet', [32]],
+            ['utc_offset', 'u16'],
+            ['streak', GmStreak],
+            ['display_name', 'string'],
+            ['avatar', { kind: 'option', type: Avatar }],
+            ['bio', 'string']]
+    }],
+    [GmStreak,
+    {
+        kind: 'struct',
+        fields: [
+            ['current', 'u32'],
+            ['longest', 'u32'],
+            ['last_day', 'u64']]
+    }],
+    [Avatar,
+    {
+        kind: 'struct',
+        fields: [
+            ['uri', 'string'],
+            ['content_hash', [32]]]
+    }]]);
+
+/**
+ * Details shown for a wallet, set with `createProfile` and `updateProfile`
+ */
+export interface ProfileDetails {
+    displayName: string;
+    avatar: { uri: string, contentHash: Uint8Array } | null;
+    bio: string;
+    utcOffsetMinutes: number;
+}
+
+/**
+ * Borsh class and schema definition for name records
+ */
+
+class NameRecord {
+    owner = new Uint8Array(32);
+    registered_at = new BN(0);
+    name = '';
+    constructor(fields: {
+        owner: Uint8Array,
+        registered_at: BN,
+        name: string,
+    } | undefined = undefined) {
+      if (fields) {
+        this.owner = fields.owner;
+        this.registered_at = fields.registered_at;
+        this.name = fields.name;
+      }
+    }
+}
+
+class ReverseRecord {
+    wallet = new Uint8Array(32);
+    name = '';
+    constructor(fields: {
+        wallet: Uint8Array,
+        name: string,
+    } | undefined = undefined) {
+      if (fields) {
+        this.wallet = fields.wallet;
+        this.name = fields.name;
+      }
+    }
+}
+
+const NAME_RECORD_SCHEMA = new Map<Function, any>([
+    [NameRecord,
+    {
+        kind: 'struct',
+        fields: [
+            ['owner', [32]],
+            ['registered_at', 'u64'],
+            ['name', 'string']]
+    }],
+    [ReverseRecord,
+    {
+        kind: 'struct',
+        fields: [
+            ['wallet', [32]],
+            ['name', 'string']]
+    }]]);
+
+/**
+ * Borsh classes and schema definition for the config of the program. Borsh looks up the
+ * schema by class, so settings must be built from these classes rather than plain objects.
+ * Fields default to the settings in effect until the config is initialized, see
+ * `ConfigSettings::default` in src/state.rs.
+ */
+
+export class RateLimits {
+    cooldown_slots = new BN(150);
+    quota_capacity = new BN(10);
+    quota_refill_slots = new BN(150);
+    constructor(fields: {
+        cooldown_slots: BN,
+        quota_capacity: BN,
+        quota_refill_slots: BN,
+    } | undefined = undefined) {
+      if (fields) {
+        this.cooldown_slots = fields.cooldown_slots;
+        this.quota_capacity = fields.quota_capacity;
+        this.quota_refill_slots = fields.quota_refill_slots;
+      }
+    }
+}
+
+export class FeeSchedule {
+    say_gm_lamports = new BN(0);
+    say_gm_to_lamports = new BN(0);
+    constructor(fields: {
+        say_gm_lamports: BN,
+        say_gm_to_lamports: BN,
+    } | undefined = undefined) {
+      if (fields) {
+        this.say_gm_lamports = fields.say_gm_lamports;
+        this.say_gm_to_lamports = fields.say_gm_to_lamports;
+      }
+    }
+}
+
+export class ConfigSettings {
+    max_name_len = 64;
+    rate_limits = new RateLimits();
+    fees = new FeeSchedule();
+    timelock_slots = new BN(0);
+    constructor(fields: {
+        max_name_len: number,
+        rate_limits: RateLimits,
+        fees: FeeSchedule,
+        timelock_slots: BN,
+    } | undefined = undefined) {
+      if (fields) {
+        this.max_name_len = fields.max_name_len;
+        this.rate_limits = fields.rate_limits;
+        this.fees = fields.fees;
+        this.timelock_slots = fields.timelock_slots;
+      }
+    }
+}
+
+class PendingSettings {
+    settings = new ConfigSettings();
+    effective_slot = new BN(0);
+    constructor(fields: {
+        settings: ConfigSettings,
+        effective_slot: BN,
+    } | undefined = undefined) {
+      if (fields) {
+        this.settings = fields.settings;
+        this.effective_slot = fields.effective_slot;
+      }
+    }
+}
+
+class PendingAdmin {
+    admin = new Uint8Array(32);
+    effective_slot = new BN(0);
+    constructor(fields: {
+        admin: Uint8Array,
+        effective_slot: BN,
+    } | undefined = undefined) {
+      if (fields) {
+        this.admin = fields.admin;
+        this.effective_slot = fields.effective_slot;
+      }
+    }
+}
+
+class PendingFeeExempt {
+    fee_exempt: Uint8Array[] = [];
+    effective_slot = new BN(0);
+    constructor(fields: {
+        fee_exempt: Uint8Array[],
+        effective_slot: BN,
+    } | undefined = undefined) {
+      if (fields) {
+        this.fee_exempt = fields.fee_exempt;
+        this.effective_slot = fields.effective_slot;
+      }
+    }
+}
+
+class Config {
+    admin = new Uint8Array(32);
+    settings = new ConfigSettings();
+    paused_instructions = new BN(0);
+    pending_settings: PendingSettings | null = null;
+    pending_admin: PendingAdmin | null = null;
+    fee_exempt: Uint8Array[] = [];
+    pending_fee_exempt: PendingFeeExempt | null = null;
+    constructor(fields: {
+        admin: Uint8Array,
+        settings: ConfigSettings,
+        paused_instructions: BN,
+        pending_settings: PendingSettings | null,
+        pending_admin: PendingAdmin | null,
+        fee_exempt: Uint8Array[],
+        pending_fee_exempt: PendingFeeExempt | null,
+    } | undefined = undefined) {
+      if (fields) {
+        this.admin = fields.admin;
+        this.settings = fields.settings;
+        this.paused_instructions = fields.paused_instructions;
+        this.pending_settings = fields.pending_settings;
+        this.pending_admin = fields.pending_admin;
+        this.fee_exempt = fields.fee_exempt;
+        this.pending_fee_exempt = fields.pending_fee_exempt;
+      }
+    }
+}
+
+const CONFIG_SCHEMA = new Map<Function, any>([
+    [Config,
+    {
+        kind: 'struct',
+        fields: [
+            ['admin', [32]],
+            ['settings', ConfigSettings],
+            ['paused_instructions', 'u64'],
+            ['pending_settings', { kind: 'option', type: PendingSettings }],
+            ['pending_admin', { kind: 'option', type: PendingAdmin }],
+            ['fee_exempt', [[32]]],
+            ['pending_fee_exempt', { kind: 'option', type: PendingFeeExempt }]]
+    }],
+    [PendingSettings,
+    {
+        kind: 'struct',
+        fields: [
+            ['settings', ConfigSettings],
+            ['effective_slot', 'u64']]
+    }],
+    [PendingFeeExempt,
+    {
+        kind: 'struct',
+        fields: [
+            ['fee_exempt', [[32]]],
+            ['effective_slot', 'u64']]
+    }],
+    [PendingAdmin,
+    {
+        kind: 'struct',
+        fields: [
+            ['admin', [32]],
+            ['effective_slot', 'u64']]
+    }],
+    [ConfigSettings,
+    {
+        kind: 'struct',
+        fields: [
+            ['max_name_len', 'u16'],
+            ['rate_limits', RateLimits],
+            ['fees', FeeSchedule],
+            ['timelock_slots', 'u64']]
+    }],
+    [RateLimits,
+    {
+        kind: 'struct',
+        fields: [
+            ['cooldown_slots', 'u64'],
+            ['quota_capacity', 'u64'],
+            ['quota_refill_slots', 'u64']]
+    }],
+    [FeeSchedule,
+    {
+        kind: 'struct',
+        fields: [
+            ['say_gm_lamports', 'u64'],
+            ['say_gm_to_lamports', 'u64']]
+    }]]);
+
+/**
+ * Number of GMs an inbox page holds, see `InboxPage::CAPACITY` in src/state.rs
+ */
+const INBOX_PAGE_CAPACITY = 32;
+
+/**
+ * Number of GMs an outbox page holds, see `OutboxPage::CAPACITY` in src/state.rs
+ */
+const OUTBOX_PAGE_CAPACITY = 32;
+
+/**
+ * Seed prefix of greeting account addresses, see `GREETING_SEED` in src/state.rs
+ */
+const GREETING_SEED = Buffer.from('greeting');
+
+/**
+ * Prefix hashed with names into greeting account seeds, see `GREETING_NAME_NAMESPACE` in src/state.rs
+ */
+const GREETING_NAME_NAMESPACE = Buffer.from('gm_program:greeting_name:');
+
+/**
+ * Seed prefix of greeter record addresses, see `GREETER_RECORD_SEED` in src/state.rs
+ */
+const GREETER_RECORD_SEED = Buffer.from('greeter');
+
+/**
+ * Seed prefix of sender record addresses, see `SENDER_RECORD_SEED` in src/state.rs
+ */
+const SENDER_RECORD_SEED = Buffer.from('sender');
+
+/**
+ * Seed prefixes of inbox header and page addresses, see `INBOX_SEED` and `INBOX_PAGE_SEED` in src/state.rs
+ */
+const INBOX_SEED = Buffer.from('inbox');
+const INBOX_PAGE_SEED = Buffer.from('inbox_page');
+
+/**
+ * Seed prefixes of outbox header and page addresses, see `OUTBOX_SEED` and `OUTBOX_PAGE_SEED` in src/state.rs
+ */
+const OUTBOX_SEED = Buffer.from('outbox');
+const OUTBOX_PAGE_SEED = Buffer.from('outbox_page');
+
+/**
+ * Seed prefix of profile addresses, see `PROFILE_SEED` in src/state.rs
+ */
+const PROFILE_SEED = Buffer.from('profile');
+
+/**
+ * Seed prefix of name record addresses, see `NAME_RECORD_SEED` in src/state.rs
+ */
+const NAME_RECORD_SEED = Buffer.from('name');
+
+/**
+ * Prefix hashed with names into name record seeds, see `NAME_RECORD_NAMESPACE` in src/state.rs
+ */
+const NAME_RECORD_NAMESPACE = Buffer.from('gm_program:name_record:');
+
+/**
+ * Seed prefix of reverse record addresses, see `REVERSE_RECORD_SEED` in src/state.rs
+ */
+const REVERSE_RECORD_SEED = Buffer.from('reverse');
+
+/**
+ * Seed of the config address, see `CONFIG_SEED` in src/state.rs
+ */
+const CONFIG_SEED = Buffer.from('config');
+
+/**
+ * Seed of the treasury address, see `TREASURY_SEED` in src/state.rs
+ */
+const TREASURY_SEED = Buffer.from('treasury');
+
+/**
+ * The loader `solana program deploy` deploys programs with
+ */
+const BPF_LOADER_UPGRADEABLE_PROGRAM_ID = new PublicKey(
+    'BPFLoaderUpgradeab1e11111111111111111111111',
+);
+
+/**
+ * Leading marker bytes and version of a typed instruction, see `GmInstruction` in src/instruction.rs
+ */
+const INSTRUCTION_HEADER = Buffer.from([0xff, 0xff, 1]);
+
+/**
+ * Borsh tags of the `GmInstruction` variants
+ */
+export enum GmInstructionTag {
+    Initialize = 0,
+    SayGm = 1,
+    Update = 2,
+    Migrate = 3,
+    Close = 4,
+    InitializeWithHistory = 5,
+    SayGmTo = 6,
+    SetUtcOffset = 7,
+    CreateProfile = 8,
+    UpdateProfile = 9,
+    CloseProfile = 10,
+    RegisterName = 11,
+    TransferName = 12,
+    ReleaseName = 13,
+    SayGmToName = 14,
+    SetPrimaryName = 15,
+    InitializeConfig = 16,
+    UpdateConfig = 17,
+    SetPausedInstructions = 18,
+    ProposeAdmin = 19,
+    AcceptAdmin = 20,
+    CancelProposals = 21,
+    SetFeeExempt = 22,
+    WithdrawTreasury = 23,
+}
+
+/**
+ * Set of paused instructions, see `GmInstruction::pause_bit` in src/instruction.rs.
+ * Instructions closing accounts or managing the config cannot be paused.
+ */
+export function pausedInstructions(...tags: GmInstructionTag[]): BN {
+    return tags.reduce((bits, tag) => bits.or(new BN(1).shln(tag)), new BN(0));
+}
+
+/**
+ * Borsh encoding of a string: u32 little-endian byte length followed by the UTF-8 bytes
+ */
+function encodeString(value: string): Buffer {
+    const bytes = Buffer.from(value, 'utf8');
+    const length = Buffer.alloc(4);
+    length.writeUInt32LE(bytes.length);
+    return Buffer.concat([length, bytes]);
+}
+
+/**
+ * Borsh encoding of profile details, in the field order of `CreateProfile` and `UpdateProfile`
+ */
+function encodeProfileDetails(details: ProfileDetails): Buffer {
+    const avatar = details.avatar === null
+        ? Buffer.from([0])
+        : Buffer.concat([
+            Buffer.from([1]),
+            encodeString(details.avatar.uri),
+            Buffer.from(details.avatar.contentHash),
+        ]);
+    const offset = Buffer.alloc(2);
+    offset.writeInt16LE(details.utcOffsetMinutes);
+    return Buffer.concat([
+        encodeString(details.displayName),
+        avatar,
+        encodeString(details.bio),
+        offset,
+    ]);
+}
+
+/**
+ * Build the data of a typed instruction from its tag and already encoded fields
+ */
+function encodeInstruction(tag: GmInstructionTag, ...fields: Buffer[]): Buffer {
+    return Buffer.concat([INSTRUCTION_HEADER, Buffer.from([tag]), ...fields]);
+}
+
+/**
+ * Longest name, in bytes, a greeting account can hold, see `GreetingAccount::MAX_NAME_LEN`
+ */
+const MAX_NAME_LEN = 64;
+
+/**
+ * Discriminators at the start of program accounts, followed by the layout version
+ */
+const GREETING_DISCRIMINATOR = Buffer.from('GREETING');
+const INBOX_HEADER_DISCRIMINATOR = Buffer.from('INBOXHDR');
+const INBOX_PAGE_DISCRIMINATOR = Buffer.from('INBOXPAG');
+const OUTBOX_DISCRIMINATOR = Buffer.from('OUTBOXGM');
+const OUTBOX_PAGE_DISCRIMINATOR = Buffer.from('OUTBOXPG');
+const PROFILE_DISCRIMINATOR = Buffer.from('PROFILEE');
+const NAME_RECORD_DISCRIMINATOR = Buffer.from('NAMERECD');
+const REVERSE_RECORD_DISCRIMINATOR = Buffer.from('REVERSEN');
+const CONFIG_DISCRIMINATOR = Buffer.from('GMCONFIG');
+const ACCOUNT_VERSION = 1;
+const HEADER_LEN = 8 + 1;
+
+/**
+ * Decode the state of a program account after checking its header
+ */
+function decodeProgramAccount<T>(
+    schema: Map<Function, any>,
+    classType: { new (args: any): T },
+    discriminator: Buffer,
+    data: Buffer,
+): T {
+    if (
+        !data.slice(0, 8).equals(discriminator) ||
+        data[8] !== ACCOUNT_VERSION
+    ) {
+        throw new Error(`Account does not hold a ${classType.name}`);
+    }
+    // The state is followed by zeroed padding, which a strict deserialize would reject
+    return borsh.deserializeUnchecked(schema, classType, data.slice(HEADER_LEN));
+}
+
+/**
+ * Number of GMs kept in the history of the greeting account we create
+ */
+const HISTORY_CAPACITY = 8;
+
+/**
+ * Size of each entry of a greeting history: greeter, name hash and timestamp
+ */
+const HISTORY_ENTRY_SIZE = 32 + 32 + 8;
+
+/**
+ * The size of each greeting account: the header, the authority, three timestamps, two counters,
+ * the optional first greeter, the u32 name length and the name at full capacity, then
+ * the history capacity, head, u32 entry count and entries
  */
-const GREETING_SIZE = borsh.serialize(
-    GmAccount.schema,
-    new GmAccount({ name: NAME_FOR_GM }))
-.length;
+const GREETING_SIZE =
+    HEADER_LEN + 32 + 8 + 8 + 8 + 8 + 8 + (1 + 32) + 4 + MAX_NAME_LEN +
+    1 + 1 + 4 + HISTORY_CAPACITY * HISTORY_ENTRY_SIZE;
 
 /**
  * Establish a connection to the cluster
@@ -160,12 +1001,35 @@ export async function checkProgram(): Promise<void> {
     }
     console.log(`Using program ${programId.toBase58()}`);
 
-    // Derive the address (public key) of a greeting account from the program so that it's easy to find later.
-    greetedPubkey = await PublicKey.createWithSeed(
+    // Derive the address (public key) of the greeting account the program creates for our name
+    greetedPubkey = await findGreetingAddress(payer.publicKey, NAME_FOR_GM);
+
+    // Move a greeting account created by earlier versions of this client to the current layout
+    const legacyPubkey = await PublicKey.createWithSeed(
         payer.publicKey,
         NAME_FOR_GM,
         programId,
     );
+    const legacyAccount = await connection.getAccountInfo(legacyPubkey);
+    if (legacyAccount !== null && legacyAccount.owner.equals(programId)) {
+        console.log('Migrating legacy account', legacyPubkey.toBase58());
+        const instruction = new TransactionInstruction({
+            keys: [
+                { pubkey: payer.publicKey, isSigner: true, isWritable: true },
+                { pubkey: legacyPubkey, isSigner: false, isWritable: true },
+                { pubkey: greetedPubkey, isSigner: false, isWritable: true },
+                { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+                { pubkey: await findConfigAddress(), isSigner: false, isWritable: false },
+            ],
+            programId,
+            data: encodeInstruction(GmInstructionTag.Migrate, encodeString(NAME_FOR_GM)),
+        });
+        await sendAndConfirmTransaction(
+            connection,
+            new Transaction().add(instruction),
+            [payer],
+        );
+    }
 
     // Check if the greeting account has already been created
     const greetedAccount = await connection.getAccountInfo(greetedPubkey);
@@ -175,25 +1039,49 @@ export async function checkProgram(): Promise<void> {
             greetedPubkey.toBase58(),
             'to say hello to',
         );
-        const lamports = await connection.getMinimumBalanceForRentExemption(
-            GREETING_SIZE,
-        );
 
-        const transaction = new Transaction().add(
-            SystemProgram.createAccountWithSeed({
-                fromPubkey: payer.publicKey,
-                basePubkey: payer.publicKey,
-                seed: NAME_FOR_GM,
-                newAccountPubkey: greetedPubkey,
-                lamports,
-                space: GREETING_SIZE,
-                programId,
-            }),
+        // The program sizes and funds the account itself
+        const instruction = new TransactionInstruction({
+            keys: [
+                { pubkey: payer.publicKey, isSigner: true, isWritable: true },
+                { pubkey: greetedPubkey, isSigner: false, isWritable: true },
+                { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+                { pubkey: await findConfigAddress(), isSigner: false, isWritable: false },
+            ],
+            programId,
+            data: encodeInstruction(
+                GmInstructionTag.InitializeWithHistory,
+                encodeString(NAME_FOR_GM),
+                Buffer.from([HISTORY_CAPACITY]),
+            ),
+        });
+        await sendAndConfirmTransaction(
+            connection,
+            new Transaction().add(instruction),
+            [payer],
         );
-        await sendAndConfirmTransaction(connection, transaction, [payer]);
     }
 }
 
+/**
+ * Derive the address of the greeting account created by a payer for a name, from a hash
+ * of the normalized name, see `find_greeting_address` in src/state.rs
+ */
+export async function findGreetingAddress(
+    greetingPayer: PublicKey,
+    name: string,
+): Promise<PublicKey> {
+    const nameSeed = createHash('sha256')
+        .update(GREETING_NAME_NAMESPACE)
+        .update(Buffer.from(normalizeName(name), 'utf8'))
+        .digest();
+    const [address] = await PublicKey.findProgramAddress(
+        [GREETING_SEED, greetingPayer.toBuffer(), nameSeed],
+        programId,
+    );
+    return address;
+}
+
 /**
  * Say GM
  */
@@ -201,21 +1089,25 @@ export async function sayGm(): Promise<void> {
 
     console.log('Saying hello to ',NAME_FOR_GM, ' with key ', greetedPubkey.toBase58());
 
-    //first we serialize the name data
-
-    let gm = new GmAccount({
-        name: NAME_FOR_GM
-    })
-
-
-    let data = borsh.serialize(GmAccount.schema, gm);
-    const data_to_send = Buffer.from(data);
-    console.log(data_to_send)
+    // Record of our GMs to the account, created by the program on the first one
+    const [recordPubkey] = await PublicKey.findProgramAddress(
+        [GREETER_RECORD_SEED, greetedPubkey.toBuffer(), payer.publicKey.toBuffer()],
+        programId,
+    );
 
     const instruction = new TransactionInstruction({
-        keys: [{ pubkey: greetedPubkey, isSigner: false, isWritable: true }],
+        keys: [
+            { pubkey: greetedPubkey, isSigner: false, isWritable: true },
+            { pubkey: payer.publicKey, isSigner: true, isWritable: true },
+            { pubkey: recordPubkey, isSigner: false, isWritable: true },
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            ...await outboxKeys(),
+            { pubkey: await findProfileAddress(payer.publicKey), isSigner: false, isWritable: true },
+            { pubkey: await findTreasuryAddress(), isSigner: false, isWritable: true },
+            { pubkey: await findConfigAddress(), isSigner: false, isWritable: false },
+        ],
         programId,
-        data: data_to_send
+        data: encodeInstruction(GmInstructionTag.SayGm),
     });
     await sendAndConfirmTransaction(
         connection,
@@ -232,14 +1124,768 @@ export async function reportGm(): Promise<void> {
     if (accountInfo === null) {
         throw 'Error: cannot find the greeted account';
     }
-    const greeting = borsh.deserialize(
+    const greeting = decodeProgramAccount(
         GmAccount.schema,
         GmAccount,
+        GREETING_DISCRIMINATOR,
         accountInfo.data,
     );
     console.log(
         greetedPubkey.toBase58(),
         'GM was said to ',
-        greeting.name
+        greeting.name,
+        'owned by',
+        new PublicKey(greeting.authority).toBase58(),
+        'last greeted at',
+        new Date(greeting.last_gm_unix_timestamp.toNumber() * 1000).toISOString(),
+        'in slot',
+        greeting.last_gm_slot.toString(),
+    );
+    console.log(
+        'GM was said',
+        greeting.gm_count.toString(),
+        'times by',
+        greeting.distinct_greeters.toString(),
+        'greeters, first by',
+        greeting.first_greeter ? new PublicKey(greeting.first_greeter).toBase58() : 'nobody',
     );
-}
\ No newline at end of file
+    for (const entry of greeting.history.recent()) {
+        console.log(
+            '  GM by',
+            new PublicKey(entry.greeter).toBase58(),
+            'at',
+            new Date(entry.unix_timestamp.toNumber() * 1000).toISOString(),
+        );
+    }
+}
+
+/**
+ * Close the greeting account and get its rent back
+ */
+export async function closeGm(): Promise<void> {
+    console.log('Closing account', greetedPubkey.toBase58());
+
+    const instruction = new TransactionInstruction({
+        keys: [
+            { pubkey: greetedPubkey, isSigner: false, isWritable: true },
+            { pubkey: payer.publicKey, isSigner: true, isWritable: false },
+            { pubkey: payer.publicKey, isSigner: false, isWritable: true },
+            { pubkey: await findConfigAddress(), isSigner: false, isWritable: false },
+        ],
+        programId,
+        data: encodeInstruction(GmInstructionTag.Close),
+    });
+    await sendAndConfirmTransaction(
+        connection,
+        new Transaction().add(instruction),
+        [payer],
+    );
+}
+
+/**
+ * Derive the address of the record of our GMs to a recipient, which holds the cooldown between them
+ */
+export async function findSenderRecordAddress(recipient: PublicKey): Promise<PublicKey> {
+    const [address] = await PublicKey.findProgramAddress(
+        [SENDER_RECORD_SEED, recipient.toBuffer(), payer.publicKey.toBuffer()],
+        programId,
+    );
+    return address;
+}
+
+/**
+ * Derive the address of the inbox header of a recipient
+ */
+export async function findInboxAddress(recipient: PublicKey): Promise<PublicKey> {
+    const [address] = await PublicKey.findProgramAddress(
+        [INBOX_SEED, recipient.toBuffer()],
+        programId,
+    );
+    return address;
+}
+
+/**
+ * Derive the address of a page of the inbox of a recipient
+ */
+export async function findInboxPageAddress(
+    recipient: PublicKey,
+    index: number,
+): Promise<PublicKey> {
+    const indexBytes = Buffer.alloc(4);
+    indexBytes.writeUInt32LE(index);
+    const [address] = await PublicKey.findProgramAddress(
+        [INBOX_PAGE_SEED, recipient.toBuffer(), indexBytes],
+        programId,
+    );
+    return address;
+}
+
+/**
+ * Fetch the inbox header of a recipient, null before anyone said GM to it
+ */
+export async function getInbox(recipient: PublicKey): Promise<InboxHeader | null> {
+    const accountInfo = await connection.getAccountInfo(await findInboxAddress(recipient));
+    if (accountInfo === null) {
+        return null;
+    }
+    return decodeProgramAccount(
+        INBOX_SCHEMA,
+        InboxHeader,
+        INBOX_HEADER_DISCRIMINATOR,
+        accountInfo.data,
+    );
+}
+
+/**
+ * Say GM to a recipient, appending it to the recipient's inbox
+ */
+export async function sayGmTo(recipient: PublicKey): Promise<void> {
+    console.log('Saying GM to', recipient.toBase58());
+
+    const [inboxKeys, senderKeys] = await sayGmToKeys(recipient);
+    const instruction = new TransactionInstruction({
+        keys: [
+            { pubkey: payer.publicKey, isSigner: true, isWritable: true },
+            { pubkey: recipient, isSigner: false, isWritable: false },
+            ...inboxKeys,
+            ...senderKeys,
+            { pubkey: await findConfigAddress(), isSigner: false, isWritable: false },
+        ],
+        programId,
+        data: encodeInstruction(GmInstructionTag.SayGmTo),
+    });
+    await sendAndConfirmTransaction(
+        connection,
+        new Transaction().add(instruction),
+        [payer],
+    );
+}
+
+/**
+ * Accounts of `SayGmTo` following the sender and recipient: the inbox header and current
+ * page of the recipient, then the system program, outbox header, current outbox page and
+ * profile of the sender, the treasury and the sender record
+ */
+async function sayGmToKeys(recipient: PublicKey): Promise<[AccountMeta[], AccountMeta[]]> {
+    // The program starts a new page once the current one is full
+    const inbox = await getInbox(recipient);
+    const pageIndex = inbox === null
+        ? 0
+        : inbox.gm_count.divn(INBOX_PAGE_CAPACITY).toNumber();
+
+    return [
+        [
+            { pubkey: await findInboxAddress(recipient), isSigner: false, isWritable: true },
+            { pubkey: await findInboxPageAddress(recipient, pageIndex), isSigner: false, isWritable: true },
+        ],
+        [
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            ...await outboxKeys(),
+            { pubkey: await findProfileAddress(payer.publicKey), isSigner: false, isWritable: true },
+            { pubkey: await findTreasuryAddress(), isSigner: false, isWritable: true },
+            { pubkey: await findSenderRecordAddress(recipient), isSigner: false, isWritable: true },
+        ],
+    ];
+}
+
+/**
+ * Iterate over the GMs in the inbox of a recipient, page by page from the oldest
+ */
+export async function* iterateInboxPages(
+    recipient: PublicKey,
+): AsyncGenerator<InboxPage> {
+    const inbox = await getInbox(recipient);
+    if (inbox === null) {
+        return;
+    }
+    for (let index = 0; index < inbox.page_count; index++) {
+        const accountInfo = await connection.getAccountInfo(
+            await findInboxPageAddress(recipient, index),
+        );
+        if (accountInfo === null) {
+            throw new Error(`Inbox page ${index} is missing`);
+        }
+        yield decodeProgramAccount(
+            INBOX_SCHEMA,
+            InboxPage,
+            INBOX_PAGE_DISCRIMINATOR,
+            accountInfo.data,
+        );
+    }
+}
+
+/**
+ * Derive the address of the outbox of a sender
+ */
+export async function findOutboxAddress(sender: PublicKey): Promise<PublicKey> {
+    const [address] = await PublicKey.findProgramAddress(
+        [OUTBOX_SEED, sender.toBuffer()],
+        programId,
+    );
+    return address;
+}
+
+/**
+ * Derive the address of a page of the outbox of a sender
+ */
+export async function findOutboxPageAddress(
+    sender: PublicKey,
+    index: number,
+): Promise<PublicKey> {
+    const indexBytes = Buffer.alloc(4);
+    indexBytes.writeUInt32LE(index);
+    const [address] = await PublicKey.findProgramAddress(
+        [OUTBOX_PAGE_SEED, sender.toBuffer(), indexBytes],
+        programId,
+    );
+    return address;
+}
+
+/**
+ * Fetch the outbox header of a sender, null before its first GM
+ */
+export async function getOutbox(sender: PublicKey): Promise<Outbox | null> {
+    const accountInfo = await connection.getAccountInfo(await findOutboxAddress(sender));
+    if (accountInfo === null) {
+        return null;
+    }
+    return decodeProgramAccount(
+        OUTBOX_SCHEMA,
+        Outbox,
+        OUTBOX_DISCRIMINATOR,
+        accountInfo.data,
+    );
+}
+
+/**
+ * Accounts of the outbox of the payer in `SayGm` and `SayGmTo`: the header and the current page
+ */
+async function outboxKeys(): Promise<AccountMeta[]> {
+    // The program starts a new page once the current one is full
+    const outbox = await getOutbox(payer.publicKey);
+    const pageIndex = outbox === null
+        ? 0
+        : outbox.gm_count.divn(OUTBOX_PAGE_CAPACITY).toNumber();
+
+    return [
+        { pubkey: await findOutboxAddress(payer.publicKey), isSigner: false, isWritable: true },
+        { pubkey: await findOutboxPageAddress(payer.publicKey, pageIndex), isSigner: false, isWritable: true },
+    ];
+}
+
+/**
+ * Iterate over the GMs said by a sender, page by page from the oldest
+ */
+export async function* iterateOutboxPages(
+    sender: PublicKey,
+): AsyncGenerator<OutboxPage> {
+    const outbox = await getOutbox(sender);
+    if (outbox === null) {
+        return;
+    }
+    for (let index = 0; index < outbox.page_count; index++) {
+        const accountInfo = await connection.getAccountInfo(
+            await findOutboxPageAddress(sender, index),
+        );
+        if (accountInfo === null) {
+            throw new Error(`Outbox page ${index} is missing`);
+        }
+        yield decodeProgramAccount(
+            OUTBOX_SCHEMA,
+            OutboxPage,
+            OUTBOX_PAGE_DISCRIMINATOR,
+            accountInfo.data,
+        );
+    }
+}
+
+/**
+ * Derive the address of the profile of a wallet
+ */
+export async function findProfileAddress(wallet: PublicKey): Promise<PublicKey> {
+    const [address] = await PublicKey.findProgramAddress(
+        [PROFILE_SEED, wallet.toBuffer()],
+        programId,
+    );
+    return address;
+}
+
+/**
+ * Fetch the profile of a wallet, null before its first GM
+ */
+export async function getProfile(wallet: PublicKey): Promise<Profile | null> {
+    const accountInfo = await connection.getAccountInfo(await findProfileAddress(wallet));
+    if (accountInfo === null) {
+        return null;
+    }
+    return decodeProgramAccount(
+        PROFILE_SCHEMA,
+        Profile,
+        PROFILE_DISCRIMINATOR,
+        accountInfo.data,
+    );
+}
+
+/**
+ * Set the time zone in which the days of our GM streak are counted, in minutes from UTC
+ */
+export async function setUtcOffset(utcOffsetMinutes: number): Promise<void> {
+    const offset = Buffer.alloc(2);
+    offset.writeInt16LE(utcOffsetMinutes);
+
+    const instruction = new TransactionInstruction({
+        keys: [
+            { pubkey: payer.publicKey, isSigner: true, isWritable: true },
+            { pubkey: await findProfileAddress(payer.publicKey), isSigner: false, isWritable: true },
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: await findConfigAddress(), isSigner: false, isWritable: false },
+        ],
+        programId,
+        data: encodeInstruction(GmInstructionTag.SetUtcOffset, offset),
+    });
+    await sendAndConfirmTransaction(
+        connection,
+        new Transaction().add(instruction),
+        [payer],
+    );
+}
+
+/**
+ * Create our profile with the details shown for us
+ */
+export async function createProfile(details: ProfileDetails): Promise<void> {
+    const instruction = new TransactionInstruction({
+        keys: [
+            { pubkey: payer.publicKey, isSigner: true, isWritable: true },
+            { pubkey: await findProfileAddress(payer.publicKey), isSigner: false, isWritable: true },
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: await findConfigAddress(), isSigner: false, isWritable: false },
+        ],
+        programId,
+        data: encodeInstruction(GmInstructionTag.CreateProfile, encodeProfileDetails(details)),
+    });
+    await sendAndConfirmTransaction(
+        connection,
+        new Transaction().add(instruction),
+        [payer],
+    );
+}
+
+/**
+ * Replace the details shown for us, keeping our GM streak
+ */
+export async function updateProfile(details: ProfileDetails): Promise<void> {
+    const instruction = new TransactionInstruction({
+        keys: [
+            { pubkey: payer.publicKey, isSigner: true, isWritable: false },
+            { pubkey: await findProfileAddress(payer.publicKey), isSigner: false, isWritable: true },
+            { pubkey: await findConfigAddress(), isSigner: false, isWritable: false },
+        ],
+        programId,
+        data: encodeInstruction(GmInstructionTag.UpdateProfile, encodeProfileDetails(details)),
+    });
+    await sendAndConfirmTransaction(
+        connection,
+        new Transaction().add(instruction),
+        [payer],
+    );
+}
+
+/**
+ * Close our profile and get its rent back
+ */
+export async function closeProfile(): Promise<void> {
+    const instruction = new TransactionInstruction({
+        keys: [
+            { pubkey: payer.publicKey, isSigner: true, isWritable: false },
+            { pubkey: await findProfileAddress(payer.publicKey), isSigner: false, isWritable: true },
+            { pubkey: payer.publicKey, isSigner: false, isWritable: true },
+            { pubkey: await findConfigAddress(), isSigner: false, isWritable: false },
+        ],
+        programId,
+        data: encodeInstruction(GmInstructionTag.CloseProfile),
+    });
+    await sendAndConfirmTransaction(
+        connection,
+        new Transaction().add(instruction),
+        [payer],
+    );
+}
+
+/**
+ * Canonical form of a name in the registry, see `normalize_name` in src/validation.rs
+ */
+export function normalizeName(name: string): string {
+    return name.toLowerCase().normalize('NFC');
+}
+
+/**
+ * Derive the address of the record of a name, from a hash of the normalized name, see
+ * `find_name_record_address` in src/state.rs
+ */
+export async function findNameRecordAddress(name: string): Promise<PublicKey> {
+    const nameSeed = createHash('sha256')
+        .update(NAME_RECORD_NAMESPACE)
+        .update(Buffer.from(normalizeName(name), 'utf8'))
+        .digest();
+    const [address] = await PublicKey.findProgramAddress(
+        [NAME_RECORD_SEED, nameSeed],
+        programId,
+    );
+    return address;
+}
+
+/**
+ * Fetch the record of a name, null if the name is not registered
+ */
+export async function getNameRecord(name: string): Promise<NameRecord | null> {
+    const accountInfo = await connection.getAccountInfo(await findNameRecordAddress(name));
+    if (accountInfo === null) {
+        return null;
+    }
+    return decodeProgramAccount(
+        NAME_RECORD_SCHEMA,
+        NameRecord,
+        NAME_RECORD_DISCRIMINATOR,
+        accountInfo.data,
+    );
+}
+
+/**
+ * Register a name to us
+ */
+export async function registerName(name: string): Promise<void> {
+    const instruction = new TransactionInstruction({
+        keys: [
+            { pubkey: payer.publicKey, isSigner: true, isWritable: true },
+            { pubkey: await findNameRecordAddress(name), isSigner: false, isWritable: true },
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: await findReverseRecordAddress(payer.publicKey), isSigner: false, isWritable: true },
+            { pubkey: await findConfigAddress(), isSigner: false, isWritable: false },
+        ],
+        programId,
+        data: encodeInstruction(GmInstructionTag.RegisterName, encodeString(name)),
+    });
+    await sendAndConfirmTransaction(
+        connection,
+        new Transaction().add(instruction),
+        [payer],
+    );
+}
+
+/**
+ * Transfer a name registered to us to a new owner
+ */
+export async function transferName(name: string, newOwner: PublicKey): Promise<void> {
+    const instruction = new TransactionInstruction({
+        keys: [
+            { pubkey: payer.publicKey, isSigner: true, isWritable: true },
+            { pubkey: await findNameRecordAddress(name), isSigner: false, isWritable: true },
+            { pubkey: await findReverseRecordAddress(payer.publicKey), isSigner: false, isWritable: true },
+            { pubkey: await findConfigAddress(), isSigner: false, isWritable: false },
+        ],
+        programId,
+        data: encodeInstruction(GmInstructionTag.TransferName, newOwner.toBuffer()),
+    });
+    await sendAndConfirmTransaction(
+        connection,
+        new Transaction().add(instruction),
+        [payer],
+    );
+}
+
+/**
+ * Release a name registered to us and get the rent of its record back
+ */
+export async function releaseName(name: string): Promise<void> {
+    const instruction = new TransactionInstruction({
+        keys: [
+            { pubkey: payer.publicKey, isSigner: true, isWritable: false },
+            { pubkey: await findNameRecordAddress(name), isSigner: false, isWritable: true },
+            { pubkey: payer.publicKey, isSigner: false, isWritable: true },
+            { pubkey: await findReverseRecordAddress(payer.publicKey), isSigner: false, isWritable: true },
+            { pubkey: await findConfigAddress(), isSigner: false, isWritable: false },
+        ],
+        programId,
+        data: encodeInstruction(GmInstructionTag.ReleaseName),
+    });
+    await sendAndConfirmTransaction(
+        connection,
+        new Transaction().add(instruction),
+        [payer],
+    );
+}
+
+/**
+ * Say GM to the owner of a registered name
+ */
+export async function sayGmToName(name: string): Promise<void> {
+    const record = await getNameRecord(name);
+    if (record === null) {
+        throw new Error(`Name ${name} is not registered`);
+    }
+    const owner = new PublicKey(record.owner);
+    console.log('Saying GM to', record.name, 'owned by', owner.toBase58());
+
+    const [inboxKeys, senderKeys] = await sayGmToKeys(owner);
+    const instruction = new TransactionInstruction({
+        keys: [
+            { pubkey: payer.publicKey, isSigner: true, isWritable: true },
+            { pubkey: await findNameRecordAddress(name), isSigner: false, isWritable: false },
+            ...inboxKeys,
+            ...senderKeys,
+            { pubkey: await findConfigAddress(), isSigner: false, isWritable: false },
+        ],
+        programId,
+        data: encodeInstruction(GmInstructionTag.SayGmToName, encodeString(name)),
+    });
+    await sendAndConfirmTransaction(
+        connection,
+        new Transaction().add(instruction),
+        [payer],
+    );
+}
+
+/**
+ * Derive the address of the reverse record of a wallet
+ */
+export async function findReverseRecordAddress(wallet: PublicKey): Promise<PublicKey> {
+    const [address] = await PublicKey.findProgramAddress(
+        [REVERSE_RECORD_SEED, wallet.toBuffer()],
+        programId,
+    );
+    return address;
+}
+
+/**
+ * Make a name registered to us our primary name
+ */
+export async function setPrimaryName(name: string): Promise<void> {
+    const instruction = new TransactionInstruction({
+        keys: [
+            { pubkey: payer.publicKey, isSigner: true, isWritable: true },
+            { pubkey: await findNameRecordAddress(name), isSigner: false, isWritable: false },
+            { pubkey: await findReverseRecordAddress(payer.publicKey), isSigner: false, isWritable: true },
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: await findConfigAddress(), isSigner: false, isWritable: false },
+        ],
+        programId,
+        data: encodeInstruction(GmInstructionTag.SetPrimaryName),
+    });
+    await sendAndConfirmTransaction(
+        connection,
+        new Transaction().add(instruction),
+        [payer],
+    );
+}
+
+/**
+ * Resolve the primary name of a wallet, null if it has none
+ *
+ * The name is only returned if its record confirms it is registered to the wallet,
+ * so a stale or forged reverse record never resolves.
+ */
+export async function resolveName(wallet: PublicKey): Promise<string | null> {
+    const accountInfo = await connection.getAccountInfo(await findReverseRecordAddress(wallet));
+    if (accountInfo === null || !accountInfo.owner.equals(programId)) {
+        return null;
+    }
+    const reverse = decodeProgramAccount(
+        NAME_RECORD_SCHEMA,
+        ReverseRecord,
+        REVERSE_RECORD_DISCRIMINATOR,
+        accountInfo.data,
+    );
+
+    const record = await getNameRecord(reverse.name);
+    if (record === null || !new PublicKey(record.owner).equals(wallet)) {
+        return null;
+    }
+    return record.name;
+}
+
+/**
+ * Derive the address of the config of the program, passed after the accounts of every instruction
+ */
+export async function findConfigAddress(): Promise<PublicKey> {
+    const [address] = await PublicKey.findProgramAddress([CONFIG_SEED], programId);
+    return address;
+}
+
+/**
+ * Derive the address of the treasury collecting the fees paid on GMs
+ */
+export async function findTreasuryAddress(): Promise<PublicKey> {
+    const [address] = await PublicKey.findProgramAddress([TREASURY_SEED], programId);
+    return address;
+}
+
+/**
+ * Fetch the config of the program, null until it is initialized
+ */
+export async function getConfig(): Promise<Config | null> {
+    const accountInfo = await connection.getAccountInfo(await findConfigAddress());
+    if (accountInfo === null) {
+        return null;
+    }
+    return decodeProgramAccount(
+        CONFIG_SCHEMA,
+        Config,
+        CONFIG_DISCRIMINATOR,
+        accountInfo.data,
+    );
+}
+
+/**
+ * Create the config of the program with us as its admin, which we must be the upgrade authority of
+ */
+export async function initializeConfig(settings: ConfigSettings): Promise<void> {
+    // Written by the upgradeable loader on deploy, it records the upgrade authority
+    const [programDataPubkey] = await PublicKey.findProgramAddress(
+        [programId.toBuffer()],
+        BPF_LOADER_UPGRADEABLE_PROGRAM_ID,
+    );
+
+    const instruction = new TransactionInstruction({
+        keys: [
+            { pubkey: payer.publicKey, isSigner: true, isWritable: true },
+            { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+            { pubkey: programDataPubkey, isSigner: false, isWritable: false },
+            { pubkey: await findTreasuryAddress(), isSigner: false, isWritable: true },
+            { pubkey: await findConfigAddress(), isSigner: false, isWritable: true },
+        ],
+        programId,
+        data: encodeInstruction(
+            GmInstructionTag.InitializeConfig,
+            Buffer.from(borsh.serialize(CONFIG_SCHEMA, settings)),
+        ),
+    });
+    await sendAndConfirmTransaction(
+        connection,
+        new Transaction().add(instruction),
+        [payer],
+    );
+}
+
+/**
+ * Replace the settings of the program, which we must be the admin of
+ */
+export async function updateConfig(settings: ConfigSettings): Promise<void> {
+    const instruction = new TransactionInstruction({
+        keys: [
+            { pubkey: payer.publicKey, isSigner: true, isWritable: false },
+            { pubkey: await findConfigAddress(), isSigner: false, isWritable: true },
+        ],
+        programId,
+        data: encodeInstruction(
+            GmInstructionTag.UpdateConfig,
+            Buffer.from(borsh.serialize(CONFIG_SCHEMA, settings)),
+        ),
+    });
+    await sendAndConfirmTransaction(
+        connection,
+        new Transaction().add(instruction),
+        [payer],
+    );
+}
+
+/**
+ * Halt the given instructions and resume all others, as the admin of the program
+ */
+export async function setPausedInstructions(...tags: GmInstructionTag[]): Promise<void> {
+    const instruction = new TransactionInstruction({
+        keys: [
+            { pubkey: payer.publicKey, isSigner: true, isWritable: false },
+            { pubkey: await findConfigAddress(), isSigner: false, isWritable: true },
+        ],
+        programId,
+        data: encodeInstruction(
+            GmInstructionTag.SetPausedInstructions,
+            pausedInstructions(...tags).toArrayLike(Buffer, 'le', 8),
+        ),
+    });
+    await sendAndConfirmTransaction(
+        connection,
+        new Transaction().add(instruction),
+        [payer],
+    );
+}
+
+/**
+ * Send an instruction managing the config, signed by us as the admin or proposed admin
+ */
+async function sendConfigInstruction(data: Buffer): Promise<void> {
+    const instruction = new TransactionInstruction({
+        keys: [
+            { pubkey: payer.publicKey, isSigner: true, isWritable: false },
+            { pubkey: await findConfigAddress(), isSigner: false, isWritable: true },
+        ],
+        programId,
+        data,
+    });
+    await sendAndConfirmTransaction(
+        connection,
+        new Transaction().add(instruction),
+        [payer],
+    );
+}
+
+/**
+ * Propose a new admin, who can accept once the timelock of the config has passed
+ */
+export async function proposeAdmin(newAdmin: PublicKey): Promise<void> {
+    await sendConfigInstruction(
+        encodeInstruction(GmInstructionTag.ProposeAdmin, newAdmin.toBuffer()),
+    );
+}
+
+/**
+ * Become the admin of the program, after the current admin proposed us
+ */
+export async function acceptAdmin(): Promise<void> {
+    await sendConfigInstruction(encodeInstruction(GmInstructionTag.AcceptAdmin));
+}
+
+/**
+ * Withdraw the proposed admin, settings and fee exempt list
+ */
+export async function cancelProposals(): Promise<void> {
+    await sendConfigInstruction(encodeInstruction(GmInstructionTag.CancelProposals));
+}
+
+/**
+ * Exempt a wallet from the fees on GMs, or make it pay them again, as the admin of the program.
+ * The change takes effect once the timelock of the config has passed.
+ */
+export async function setFeeExempt(wallet: PublicKey, exempt: boolean): Promise<void> {
+    await sendConfigInstruction(
+        encodeInstruction(
+            GmInstructionTag.SetFeeExempt,
+            Buffer.concat([wallet.toBuffer(), Buffer.from([exempt ? 1 : 0])]),
+        ),
+    );
+}
+
+/**
+ * Send fees collected by the treasury to a destination, as the admin of the program
+ */
+export async function withdrawTreasury(destination: PublicKey, lamports: BN): Promise<void> {
+    const instruction = new TransactionInstruction({
+        keys: [
+            { pubkey: payer.publicKey, isSigner: true, isWritable: false },
+            { pubkey: await findTreasuryAddress(), isSigner: false, isWritable: true },
+            { pubkey: destination, isSigner: false, isWritable: true },
+            { pubkey: await findConfigAddress(), isSigner: false, isWritable: false },
+        ],
+        programId,
+        data: encodeInstruction(
+            GmInstructionTag.WithdrawTreasury,
+            lamports.toArrayLike(Buffer, 'le', 8),
+        ),
+    });
+    await sendAndConfirmTransaction(
+        connection,
+        new Transaction().add(instruction),
+        [payer],
+    );
+}
@@ -0,0 +1,22 @@
+use solana_program::{
+    account_info::AccountInfo, entrypoint, entrypoint::ProgramResult,
+    program_error::PrintProgramError, pubkey::Pubkey,
+};
+
+use crate::{error::GmError, processor};
+
+// Declare and export the program's entrypoint
+entrypoint!(process_instruction);
+
+fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    input: &[u8],
+) -> ProgramResult {
+    if let Err(error) = processor::process_instruction(program_id, accounts, input) {
+        // Log the human readable form of custom errors before failing the transaction
+        error.print::<GmError>();
+        return Err(error);
+    }
+    Ok(())
+}
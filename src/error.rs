@@ -0,0 +1,131 @@
+// num-derive 0.3 emits its impl inside a const block, which newer compilers lint against
+#![allow(non_local_definitions)]
+
+use num_derive::FromPrimitive;
+use solana_program::{
+    decode_error::DecodeError,
+    msg,
+    program_error::{PrintProgramError, ProgramError},
+};
+use thiserror::Error;
+
+/// Errors returned by the GM program, surfaced to clients as `ProgramError::Custom(code)`
+///
+/// The code of each variant is its position, so new variants must only ever be appended.
+#[derive(Clone, Copy, Debug, Eq, Error, FromPrimitive, PartialEq)]
+pub enum GmError {
+    /// Instruction data is neither a legacy payload nor a known `GmInstruction`
+    #[error("Invalid instruction")]
+    InvalidInstruction,
+    /// The name exceeds the capacity of a greeting account
+    #[error("Name too long")]
+    NameTooLong,
+    /// The account data cannot hold the value being written
+    #[error("Account too small")]
+    AccountTooSmall,
+    /// The account does not hold enough lamports to be rent exempt
+    #[error("Account not rent exempt")]
+    NotRentExempt,
+    /// A required signature is missing, or the signer is not the account authority
+    #[error("Unauthorized")]
+    Unauthorized,
+    /// The account is not owned by the GM program
+    #[error("Incorrect account owner")]
+    IncorrectOwner,
+    /// The account has already been initialized
+    #[error("Account already initialized")]
+    AlreadyInitialized,
+    /// The account data does not decode as the expected state
+    #[error("Invalid account data")]
+    InvalidAccountData,
+    /// The account address does not match the one derived by the program
+    #[error("Invalid account address")]
+    InvalidAccountAddress,
+    /// The account header does not carry the discriminator of the expected state
+    #[error("Invalid account type")]
+    InvalidAccountType,
+    /// The account header carries a layout version this program cannot read
+    #[error("Unsupported account version")]
+    UnsupportedAccountVersion,
+    /// An arithmetic operation overflowed
+    #[error("Arithmetic overflow")]
+    Overflow,
+    /// An account the instruction writes to was not passed as writable
+    #[error("Account not writable")]
+    AccountNotWritable,
+    /// The name is empty
+    #[error("Name empty")]
+    NameEmpty,
+    /// The name is not in Unicode normalization form C
+    #[error("Name not NFC normalized")]
+    NameNotNormalized,
+    /// The name contains a control character such as a newline
+    #[error("Name contains a control character")]
+    NameHasControlCharacter,
+    /// The name contains a character overriding or isolating the text direction
+    #[error("Name contains a bidirectional override")]
+    NameHasBidiOverride,
+    /// The name contains an invisible zero width character
+    #[error("Name contains a zero width character")]
+    NameHasZeroWidthCharacter,
+    /// The requested greeting history is larger than allowed
+    #[error("Invalid history capacity")]
+    InvalidHistoryCapacity,
+    /// The greeter is saying GM faster than the rate limits allow
+    #[error("Rate limited")]
+    RateLimited,
+    /// The UTC offset is not the one of any time zone
+    #[error("Invalid UTC offset")]
+    InvalidUtcOffset,
+    /// The avatar URI is too long, has an unsupported scheme or contains invalid characters
+    #[error("Invalid avatar URI")]
+    InvalidAvatarUri,
+    /// The name is already registered to a wallet
+    #[error("Name already registered")]
+    NameAlreadyRegistered,
+    /// The name is not registered to any wallet
+    #[error("Name not registered")]
+    NameNotRegistered,
+    /// The instruction is paused by the admin of the program
+    #[error("Instruction paused")]
+    Paused,
+    /// The config settings are out of range
+    #[error("Invalid config")]
+    InvalidConfig,
+    /// The timelock of the proposed change has not elapsed yet
+    #[error("Timelock not elapsed")]
+    TimelockNotElapsed,
+    /// No admin is proposed, or the signer is not the proposed admin
+    #[error("No matching admin proposal")]
+    NoAdminProposal,
+    /// The fee exempt list holds as many wallets as it can
+    #[error("Fee exempt list full")]
+    FeeExemptListFull,
+    /// The account does not hold enough lamports above its rent exempt minimum
+    #[error("Insufficient funds")]
+    InsufficientFunds,
+    /// The new name would no longer derive the address of the greeting account
+    #[error("Name changes account address")]
+    NameChangesAddress,
+    /// The new UTC offset would start the next day after today's GM was counted
+    #[error("UTC offset change skips to the next day")]
+    UtcOffsetSkipsDay,
+}
+
+impl From<GmError> for ProgramError {
+    fn from(e: GmError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl<T> DecodeError<T> for GmError {
+    fn type_of() -> &'static str {
+        "GmError"
+    }
+}
+
+impl PrintProgramError for GmError {
+    fn print<E>(&self) {
+        msg!("Error: {}", self);
+    }
+}
@@ -0,0 +1,475 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+use crate::{
+    error::GmError,
+    state::{Avatar, ConfigSettings, LegacyGreeting},
+};
+
+/// Leading bytes of every typed instruction. Legacy payloads start with a
+/// little-endian u32 name length, which can never reach 0xffff inside a transaction.
+pub const INSTRUCTION_MARKER: [u8; 2] = [0xff, 0xff];
+
+/// Version of the `GmInstruction` encoding that follows `INSTRUCTION_MARKER`
+pub const INSTRUCTION_VERSION: u8 = 1;
+
+/// Instructions supported by the GM program
+///
+/// Variants are encoded by Borsh as a one byte tag followed by their fields,
+/// so new variants must only ever be appended.
+///
+/// Every instruction expects the config of the program, at `find_config_address()`, after
+/// the accounts listed for it. The config does not need to be initialized.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub enum GmInstruction {
+    /// Create a greeting account for `name` and store the name in it
+    ///
+    /// The account is created by the program at `find_greeting_address(payer, name)`
+    /// and funded by the payer to be rent exempt. The payer becomes its authority.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` The payer funding the new account
+    /// 1. `[writable]` The greeting account to create
+    /// 2. `[]` The system program
+    Initialize { name: String },
+
+    /// Say GM to the name stored in a greeting account, recording when and by whom it was said
+    ///
+    /// The greeter record at `find_greeter_record_address(greeting, greeter)` is created,
+    /// funded by the greeter, on its first GM to the greeting account. The GM is also
+    /// appended to the current page of the outbox of the greeter, at
+    /// `find_outbox_page_address(greeter, index)` where `index` is the current page of the
+    /// outbox, or zero before the first GM. The greeter funds the outbox and its pages
+    /// like the record.
+    /// The greeter pays the `say_gm_lamports` fee of the config to the treasury, unless
+    /// it is fee exempt.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The greeting account
+    /// 1. `[writable, signer]` The greeter
+    /// 2. `[writable]` The greeter record
+    /// 3. `[]` The system program
+    /// 4. `[writable]` The outbox of the greeter at `find_outbox_address(greeter)`
+    /// 5. `[writable]` The current outbox page of the greeter
+    /// 6. `[writable]` The profile of the greeter at `find_profile_address(greeter)`,
+    ///    created on its first GM, where the GM streak of the greeter is kept
+    /// 7. `[writable]` The treasury at `find_treasury_address()`
+    SayGm,
+
+    /// Replace the name stored in a greeting account
+    ///
+    /// The address of the account is derived from the normalized name, so the new name may
+    /// only differ from the stored one in case and composition. A name migrated from a legacy
+    /// account that breaks the naming rules can be replaced by any valid name, the account
+    /// keeping its address.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The greeting account
+    /// 1. `[signer]` The authority of the greeting account
+    Update { name: String },
+
+    /// Move a greeting account of the legacy layout to the current one
+    ///
+    /// The legacy account must have been created by the payer with `create_account_with_seed`
+    /// and `seed`. It is rewritten in place when large enough, otherwise its greeting is
+    /// copied into a new account at `find_greeting_address(payer, name)` and it is closed,
+    /// its lamports going to the payer. The payer becomes the authority of the greeting.
+    /// The name is kept as stored, since the legacy program accepted any name, and can be
+    /// replaced with `Update` if it breaks the current rules.
+    /// Migrating an already migrated account succeeds without changes.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` The payer that created the legacy account
+    /// 1. `[writable]` The legacy greeting account
+    /// 2. `[writable]` The greeting account to create when the legacy one is too small
+    /// 3. `[]` The system program
+    Migrate { seed: String },
+
+    /// Close a greeting account, sending its lamports to the destination
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The greeting account
+    /// 1. `[signer]` The authority of the greeting account
+    /// 2. `[writable]` The account receiving the lamports
+    Close,
+
+    /// Create a greeting account like `Initialize`, with room to keep the last
+    /// `history_capacity` GMs said to it, at most `GreetingHistory::MAX_CAPACITY`
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` The payer funding the new account
+    /// 1. `[writable]` The greeting account to create
+    /// 2. `[]` The system program
+    InitializeWithHistory { name: String, history_capacity: u8 },
+
+    /// Say GM to a recipient, appending it to the recipient's inbox
+    ///
+    /// The page is the one at `find_inbox_page_address(recipient, index)` where `index`
+    /// is the current page of the inbox header, or zero before the first GM. The sender
+    /// funds the header and any page that does not exist yet. The GM is also appended to
+    /// the outbox of the sender, paged the same way, and recorded in the sender record of
+    /// the sender and recipient, which holds the cooldown between their GMs.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` The sender
+    /// 1. `[]` The recipient
+    /// 2. `[writable]` The inbox header at `find_inbox_address(recipient)`
+    /// 3. `[writable]` The current inbox page
+    /// 4. `[]` The system program
+    /// 5. `[writable]` The outbox of the sender at `find_outbox_address(sender)`
+    /// 6. `[writable]` The current outbox page of the sender
+    /// 7. `[writable]` The profile of the sender at `find_profile_address(sender)`,
+    ///    created on its first GM, where the GM streak of the sender is kept
+    /// 8. `[writable]` The treasury at `find_treasury_address()`, receiving the
+    ///    `say_gm_to_lamports` fee of the config unless the sender is fee exempt
+    /// 9. `[writable]` The sender record at `find_sender_record_address(recipient, sender)`,
+    ///    created on the first GM of the sender to the recipient
+    SayGmTo,
+
+    /// Set the time zone in which the days of the GM streak of a wallet are counted,
+    /// creating its profile if needed
+    ///
+    /// Once the GM of the current local day is counted, the time zone cannot move the
+    /// wallet to the next day before that day starts in the current time zone.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` The wallet
+    /// 1. `[writable]` The profile of the wallet at `find_profile_address(wallet)`
+    /// 2. `[]` The system program
+    SetUtcOffset { utc_offset_minutes: i16 },
+
+    /// Create the profile of a wallet, funded by the wallet, with the details shown for it
+    ///
+    /// Fails if the profile already exists, which it does once the wallet said GM:
+    /// use `UpdateProfile` then.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` The wallet
+    /// 1. `[writable]` The profile of the wallet at `find_profile_address(wallet)`
+    /// 2. `[]` The system program
+    CreateProfile {
+        display_name: String,
+        avatar: Option<Avatar>,
+        bio: String,
+        utc_offset_minutes: i16,
+    },
+
+    /// Replace the details shown for a wallet, keeping its GM streak. The time zone
+    /// changes as with `SetUtcOffset`.
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` The wallet
+    /// 1. `[writable]` The profile of the wallet
+    UpdateProfile {
+        display_name: String,
+        avatar: Option<Avatar>,
+        bio: String,
+        utc_offset_minutes: i16,
+    },
+
+    /// Close the profile of a wallet, sending its lamports to the destination.
+    /// The GM streak of the wallet is lost.
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` The wallet
+    /// 1. `[writable]` The profile of the wallet
+    /// 2. `[writable]` The account receiving the lamports
+    CloseProfile,
+
+    /// Register a name to the wallet, which pays for its record
+    ///
+    /// The name is normalized with `normalize_name` and recorded at
+    /// `find_name_record_address(normalized name)`. Fails if the name is already registered
+    /// or breaks the naming rules of the config, including its `max_name_len`.
+    /// The name becomes the primary name of the wallet if it has none yet.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` The wallet registering the name
+    /// 1. `[writable]` The name record
+    /// 2. `[]` The system program
+    /// 3. `[writable]` The reverse record of the wallet at `find_reverse_record_address(wallet)`
+    RegisterName { name: String },
+
+    /// Transfer a registered name to a new owner
+    ///
+    /// If the name was the primary name of the owner, the reverse record of the owner is
+    /// closed and its lamports sent to the owner. The new owner can make the name its
+    /// primary name with `SetPrimaryName`.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` The owner of the name
+    /// 1. `[writable]` The name record
+    /// 2. `[writable]` The reverse record of the owner
+    TransferName { new_owner: Pubkey },
+
+    /// Release a registered name so anyone can register it, sending the lamports of its
+    /// record, and of the reverse record of the owner if it was its primary name,
+    /// to the destination
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` The owner of the name
+    /// 1. `[writable]` The name record
+    /// 2. `[writable]` The account receiving the lamports
+    /// 3. `[writable]` The reverse record of the owner
+    ReleaseName,
+
+    /// Say GM to the owner of a registered name, like `SayGmTo`
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` The sender
+    /// 1. `[]` The record of the name at `find_name_record_address(normalized name)`
+    /// 2. `[writable]` The inbox header of the owner of the name
+    /// 3. `[writable]` The current page of the inbox of the owner of the name
+    /// 4. `[]` The system program
+    /// 5. `[writable]` The outbox of the sender
+    /// 6. `[writable]` The current outbox page of the sender
+    /// 7. `[writable]` The profile of the sender
+    /// 8. `[writable]` The treasury
+    /// 9. `[writable]` The sender record of the sender and the owner of the name
+    SayGmToName { name: String },
+
+    /// Make a name registered to the wallet its primary name, creating the reverse record
+    /// of the wallet if needed
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` The wallet
+    /// 1. `[]` The record of the name
+    /// 2. `[writable]` The reverse record of the wallet
+    /// 3. `[]` The system program
+    SetPrimaryName,
+
+    /// Create the config of the program, making the signer its admin, and the treasury
+    /// collecting its fees
+    ///
+    /// Only the upgrade authority of the program, as recorded in its program data account
+    /// by the upgradeable loader, can initialize the config, so nobody can claim the admin
+    /// role before the deployer does.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` The upgrade authority of the program, funding both accounts
+    /// 1. `[]` The system program
+    /// 2. `[]` The program data account of the program
+    /// 3. `[writable]` The treasury at `find_treasury_address()`
+    /// 4. `[writable]` The config
+    InitializeConfig { settings: ConfigSettings },
+
+    /// Replace the settings of the program
+    ///
+    /// With a timelock in the current settings, the settings are only proposed and take
+    /// effect once `timelock_slots` have passed, replacing any settings proposed before.
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` The admin
+    /// 1. `[writable]` The config
+    UpdateConfig { settings: ConfigSettings },
+
+    /// Halt or resume instructions at once, replacing the set of paused instructions with
+    /// the ones whose `pause_bit` is set in `paused_instructions`
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` The admin
+    /// 1. `[writable]` The config
+    SetPausedInstructions { paused_instructions: u64 },
+
+    /// Propose a new admin, who becomes admin by accepting with `AcceptAdmin` once the
+    /// timelock of the current settings has passed. Replaces any admin proposed before.
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` The admin
+    /// 1. `[writable]` The config
+    ProposeAdmin { new_admin: Pubkey },
+
+    /// Become the admin proposed by the current admin
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` The proposed admin
+    /// 1. `[writable]` The config
+    AcceptAdmin,
+
+    /// Withdraw the proposed admin, settings and fee exempt list, if any
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` The admin
+    /// 1. `[writable]` The config
+    CancelProposals,
+
+    /// Add a wallet to the fee exempt list of the config, or remove it
+    ///
+    /// Like settings, the change takes effect once `timelock_slots` have passed. It applies
+    /// to the list proposed before, if any, whose timelock starts over.
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` The admin
+    /// 1. `[writable]` The config
+    SetFeeExempt { wallet: Pubkey, exempt: bool },
+
+    /// Send collected fees from the treasury, which keeps its rent exempt minimum
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` The admin
+    /// 1. `[writable]` The treasury
+    /// 2. `[writable]` The account receiving the lamports
+    /// 3. `[]` The config
+    WithdrawTreasury { lamports: u64 },
+}
+
+/// Instruction data as received by the program, tagged with its wire format
+#[derive(Debug, Clone, PartialEq)]
+pub enum VersionedInstruction {
+    /// A bare Borsh `LegacyGreeting`, as sent by clients predating `GmInstruction`.
+    /// Says GM to the name and stores it in a greeting account of the legacy layout.
+    /// When the config is passed, it is halted along with `GmInstruction::SayGm` when the
+    /// admin pauses it. Old clients pass only the greeting account: their calls follow the
+    /// default naming rules and cannot be paused.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The legacy greeting account
+    /// 1. `[]` Optional: the config at `find_config_address()`
+    Legacy(LegacyGreeting),
+    /// `INSTRUCTION_MARKER`, version 1, then a Borsh `GmInstruction`
+    V1(GmInstruction),
+}
+
+impl VersionedInstruction {
+    /// Decode instruction data, detecting legacy payloads by the missing marker
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        match input.strip_prefix(&INSTRUCTION_MARKER[..]) {
+            Some([INSTRUCTION_VERSION, rest @ ..]) => GmInstruction::try_from_slice(rest)
+                .map(Self::V1)
+                .map_err(|_| GmError::InvalidInstruction.into()),
+            Some(_) => Err(GmError::InvalidInstruction.into()),
+            None => LegacyGreeting::try_from_slice(input)
+                .map(Self::Legacy)
+                .map_err(|_| GmError::InvalidInstruction.into()),
+        }
+    }
+}
+
+impl GmInstruction {
+    /// Encode the instruction with the marker and version expected by `VersionedInstruction::unpack`
+    pub fn pack(&self) -> Result<Vec<u8>, ProgramError> {
+        let mut data = INSTRUCTION_MARKER.to_vec();
+        data.push(INSTRUCTION_VERSION);
+        self.serialize(&mut data)?;
+        Ok(data)
+    }
+
+    /// Bit of `Config::paused_instructions` halting the instruction, the bit of its tag.
+    /// Instructions that only close accounts or manage the config cannot be paused, so users
+    /// can always get their rent back and the admin can always resume the program.
+    pub fn pause_bit(&self) -> Option<u64> {
+        let tag = match self {
+            GmInstruction::Initialize { .. } => 0,
+            GmInstruction::SayGm => 1,
+            GmInstruction::Update { .. } => 2,
+            GmInstruction::Migrate { .. } => 3,
+            GmInstruction::Close => return None,
+            GmInstruction::InitializeWithHistory { .. } => 5,
+            GmInstruction::SayGmTo => 6,
+            GmInstruction::SetUtcOffset { .. } => 7,
+            GmInstruction::CreateProfile { .. } => 8,
+            GmInstruction::UpdateProfile { .. } => 9,
+            GmInstruction::CloseProfile => return None,
+            GmInstruction::RegisterName { .. } => 11,
+            GmInstruction::TransferName { .. } => 12,
+            GmInstruction::ReleaseName => return None,
+            GmInstruction::SayGmToName { .. } => 14,
+            GmInstruction::SetPrimaryName => 15,
+            GmInstruction::InitializeConfig { .. }
+            | GmInstruction::UpdateConfig { .. }
+            | GmInstruction::SetPausedInstructions { .. }
+            | GmInstruction::ProposeAdmin { .. }
+            | GmInstruction::AcceptAdmin
+            | GmInstruction::CancelProposals
+            | GmInstruction::SetFeeExempt { .. }
+            | GmInstruction::WithdrawTreasury { .. } => return None,
+        };
+        Some(1 << tag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One instruction of every variant, in the order of their tags
+    fn every_instruction() -> Vec<GmInstruction> {
+        let name = "gm".to_string();
+        let settings = ConfigSettings::default();
+        vec![
+            GmInstruction::Initialize { name: name.clone() },
+            GmInstruction::SayGm,
+            GmInstruction::Update { name: name.clone() },
+            GmInstruction::Migrate { seed: name.clone() },
+            GmInstruction::Close,
+            GmInstruction::InitializeWithHistory {
+                name: name.clone(),
+                history_capacity: 1,
+            },
+            GmInstruction::SayGmTo,
+            GmInstruction::SetUtcOffset {
+                utc_offset_minutes: 0,
+            },
+            GmInstruction::CreateProfile {
+                display_name: name.clone(),
+                avatar: None,
+                bio: String::new(),
+                utc_offset_minutes: 0,
+            },
+            GmInstruction::UpdateProfile {
+                display_name: name.clone(),
+                avatar: None,
+                bio: String::new(),
+                utc_offset_minutes: 0,
+            },
+            GmInstruction::CloseProfile,
+            GmInstruction::RegisterName { name: name.clone() },
+            GmInstruction::TransferName {
+                new_owner: Pubkey::default(),
+            },
+            GmInstruction::ReleaseName,
+            GmInstruction::SayGmToName { name },
+            GmInstruction::SetPrimaryName,
+            GmInstruction::InitializeConfig { settings },
+            GmInstruction::UpdateConfig { settings },
+            GmInstruction::SetPausedInstructions {
+                paused_instructions: 0,
+            },
+            GmInstruction::ProposeAdmin {
+                new_admin: Pubkey::default(),
+            },
+            GmInstruction::AcceptAdmin,
+            GmInstruction::CancelProposals,
+            GmInstruction::SetFeeExempt {
+                wallet: Pubkey::default(),
+                exempt: true,
+            },
+            GmInstruction::WithdrawTreasury { lamports: 0 },
+        ]
+    }
+
+    #[test]
+    fn pause_bit_is_the_bit_of_the_borsh_tag() {
+        for (index, instruction) in every_instruction().iter().enumerate() {
+            let tag = instruction.try_to_vec().unwrap()[0];
+            assert_eq!(tag as usize, index, "{:?} is out of order", instruction);
+
+            let unpausable = matches!(
+                instruction,
+                GmInstruction::Close
+                    | GmInstruction::CloseProfile
+                    | GmInstruction::ReleaseName
+                    | GmInstruction::InitializeConfig { .. }
+                    | GmInstruction::UpdateConfig { .. }
+                    | GmInstruction::SetPausedInstructions { .. }
+                    | GmInstruction::ProposeAdmin { .. }
+                    | GmInstruction::AcceptAdmin
+                    | GmInstruction::CancelProposals
+                    | GmInstruction::SetFeeExempt { .. }
+                    | GmInstruction::WithdrawTreasury { .. }
+            );
+            let expected = if unpausable { None } else { Some(1 << tag) };
+            assert_eq!(instruction.pause_bit(), expected, "{:?}", instruction);
+        }
+    }
+}
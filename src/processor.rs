@@ -0,0 +1,1767 @@
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    hash::hash,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::GmError,
+    instruction::{GmInstruction, VersionedInstruction},
+    rate_limit::{RateLimits, TokenBucket},
+    state::{
+        find_config_address, find_greeter_record_address, find_greeting_address,
+        find_inbox_address, find_inbox_page_address, find_name_record_address, find_outbox_address,
+        find_outbox_page_address, find_profile_address, find_reverse_record_address,
+        find_sender_record_address, find_treasury_address, greeting_name_seed, name_record_seed,
+        Avatar, Config, ConfigSettings, GreeterRecord, GreetingAccount, GreetingHistory,
+        HistoryEntry, InboxEntry, InboxHeader, InboxPage, LegacyGreeting, NameRecord, Outbox,
+        OutboxEntry, OutboxPage, PendingAdmin, PendingFeeExempt, PendingSettings, Profile,
+        ProgramAccount, ReverseRecord, SenderRecord, CLOSED_ACCOUNT_DISCRIMINATOR, CONFIG_SEED,
+        GREETER_RECORD_SEED, GREETING_SEED, INBOX_PAGE_SEED, INBOX_SEED, NAME_RECORD_SEED,
+        OUTBOX_PAGE_SEED, OUTBOX_SEED, PROFILE_SEED, REVERSE_RECORD_SEED, SENDER_RECORD_SEED,
+        TREASURY_SEED,
+    },
+    validation::{normalize_name, validate_avatar_uri, NameRules},
+};
+
+// Program entrypoint's implementation
+pub fn process_instruction(
+    program_id: &Pubkey, // Public key of the account the GM program was loaded into
+    accounts: &[AccountInfo], // The accounts required by the instruction
+    input: &[u8],        // Instruction data, either a legacy greeting or a versioned GmInstruction
+) -> ProgramResult {
+    msg!("GM program entrypoint");
+
+    match VersionedInstruction::unpack(input)? {
+        VersionedInstruction::Legacy(greeting) => {
+            msg!("Instruction: SayGm (legacy)");
+            process_legacy_say_gm(program_id, accounts, greeting)
+        }
+        VersionedInstruction::V1(instruction) => {
+            process_typed_instruction(program_id, accounts, instruction)
+        }
+    }
+}
+
+/// Process a typed instruction under the config passed after its accounts
+fn process_typed_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction: GmInstruction,
+) -> ProgramResult {
+    let (config_account, accounts) = accounts
+        .split_last()
+        .ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let config = load_config(program_id, config_account)?;
+
+    if config.is_paused(&instruction) {
+        msg!("Instruction is paused by the admin");
+        return Err(GmError::Paused.into());
+    }
+
+    match instruction {
+        GmInstruction::Initialize { name } => {
+            msg!("Instruction: Initialize");
+            process_initialize(program_id, accounts, &config.settings, name, 0)
+        }
+        GmInstruction::SayGm => {
+            msg!("Instruction: SayGm");
+            process_say_gm(program_id, accounts, &config)
+        }
+        GmInstruction::Update { name } => {
+            msg!("Instruction: Update");
+            process_update(program_id, accounts, &config.settings, name)
+        }
+        GmInstruction::Migrate { seed } => {
+            msg!("Instruction: Migrate");
+            process_migrate(program_id, accounts, seed)
+        }
+        GmInstruction::Close => {
+            msg!("Instruction: Close");
+            process_close(program_id, accounts)
+        }
+        GmInstruction::InitializeWithHistory {
+            name,
+            history_capacity,
+        } => {
+            msg!("Instruction: InitializeWithHistory");
+            process_initialize(
+                program_id,
+                accounts,
+                &config.settings,
+                name,
+                history_capacity,
+            )
+        }
+        GmInstruction::SayGmTo => {
+            msg!("Instruction: SayGmTo");
+            process_say_gm_to(program_id, accounts, &config)
+        }
+        GmInstruction::SetUtcOffset { utc_offset_minutes } => {
+            msg!("Instruction: SetUtcOffset");
+            process_set_utc_offset(program_id, accounts, utc_offset_minutes)
+        }
+        GmInstruction::CreateProfile {
+            display_name,
+            avatar,
+            bio,
+            utc_offset_minutes,
+        } => {
+            msg!("Instruction: CreateProfile");
+            process_create_profile(
+                program_id,
+                accounts,
+                display_name,
+                avatar,
+                bio,
+                utc_offset_minutes,
+            )
+        }
+        GmInstruction::UpdateProfile {
+            display_name,
+            avatar,
+            bio,
+            utc_offset_minutes,
+        } => {
+            msg!("Instruction: UpdateProfile");
+            process_update_profile(
+                program_id,
+                accounts,
+                display_name,
+                avatar,
+                bio,
+                utc_offset_minutes,
+            )
+        }
+        GmInstruction::CloseProfile => {
+            msg!("Instruction: CloseProfile");
+            process_close_profile(program_id, accounts)
+        }
+        GmInstruction::RegisterName { name } => {
+            msg!("Instruction: RegisterName");
+            process_register_name(program_id, accounts, &config.settings, name)
+        }
+        GmInstruction::TransferName { new_owner } => {
+            msg!("Instruction: TransferName");
+            process_transfer_name(program_id, accounts, new_owner)
+        }
+        GmInstruction::ReleaseName => {
+            msg!("Instruction: ReleaseName");
+            process_release_name(program_id, accounts)
+        }
+        GmInstruction::SayGmToName { name } => {
+            msg!("Instruction: SayGmToName");
+            process_say_gm_to_name(program_id, accounts, &config, name)
+        }
+        GmInstruction::SetPrimaryName => {
+            msg!("Instruction: SetPrimaryName");
+            process_set_primary_name(program_id, accounts)
+        }
+        GmInstruction::InitializeConfig { settings } => {
+            msg!("Instruction: InitializeConfig");
+            process_initialize_config(program_id, accounts, config_account, settings)
+        }
+        GmInstruction::UpdateConfig { settings } => {
+            msg!("Instruction: UpdateConfig");
+            process_update_config(accounts, config_account, config, settings)
+        }
+        GmInstruction::SetPausedInstructions {
+            paused_instructions,
+        } => {
+            msg!("Instruction: SetPausedInstructions");
+            process_set_paused_instructions(accounts, config_account, config, paused_instructions)
+        }
+        GmInstruction::ProposeAdmin { new_admin } => {
+            msg!("Instruction: ProposeAdmin");
+            process_propose_admin(accounts, config_account, config, new_admin)
+        }
+        GmInstruction::AcceptAdmin => {
+            msg!("Instruction: AcceptAdmin");
+            process_accept_admin(accounts, config_account, config)
+        }
+        GmInstruction::CancelProposals => {
+            msg!("Instruction: CancelProposals");
+            process_cancel_proposals(accounts, config_account, config)
+        }
+        GmInstruction::SetFeeExempt { wallet, exempt } => {
+            msg!("Instruction: SetFeeExempt");
+            process_set_fee_exempt(accounts, config_account, config, wallet, exempt)
+        }
+        GmInstruction::WithdrawTreasury { lamports } => {
+            msg!("Instruction: WithdrawTreasury");
+            process_withdraw_treasury(program_id, accounts, &config, lamports)
+        }
+    }
+}
+
+/// Say GM to the name in the payload and store it, as the program did before `GmInstruction`.
+/// Only greeting accounts of the legacy layout are accepted, so headers are never overwritten.
+fn process_legacy_say_gm(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    greeting: LegacyGreeting,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let account = next_greeting_account(program_id, accounts_iter)?;
+    check_writable(account)?;
+
+    // Old clients do not pass the config, so only newer ones can be halted by the pause
+    let config = match accounts_iter.next() {
+        Some(config_account) => load_config(program_id, config_account)?,
+        None => Config::uninitialized(),
+    };
+    if config.is_paused(&GmInstruction::SayGm) {
+        msg!("Instruction is paused by the admin");
+        return Err(GmError::Paused.into());
+    }
+
+    check_name(&greeting.name, &config.settings)?;
+
+    if LegacyGreeting::unpack(&account.try_borrow_data()?).is_err() {
+        msg!("Greeting account has a header and must be sent a typed instruction");
+        return Err(GmError::InvalidAccountType.into());
+    }
+
+    //Say GM in the Program output
+    msg!("GM {}", greeting.name);
+
+    //Serialize the name, and store it in the passed in account
+    greeting.pack(&mut account.try_borrow_mut_data()?)?;
+
+    Ok(())
+}
+
+fn process_initialize(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    settings: &ConfigSettings,
+    name: String,
+    history_capacity: u8,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+
+    if !payer.is_signer {
+        msg!("Payer must sign to fund the greeting account");
+        return Err(GmError::Unauthorized.into());
+    }
+    check_writable(payer)?;
+    check_writable(account)?;
+
+    check_name(&name, settings)?;
+
+    if history_capacity > GreetingHistory::MAX_CAPACITY {
+        msg!(
+            "History capacity is {}, at most {} allowed",
+            history_capacity,
+            GreetingHistory::MAX_CAPACITY
+        );
+        return Err(GmError::InvalidHistoryCapacity.into());
+    }
+
+    let greeting = GreetingAccount::new(
+        *payer.key,
+        name,
+        Clock::get()?.unix_timestamp,
+        history_capacity,
+    );
+    create_greeting_account(program_id, payer, account, system_program, greeting)
+}
+
+fn process_say_gm(program_id: &Pubkey, accounts: &[AccountInfo], config: &Config) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let account = next_greeting_account(program_id, accounts_iter)?;
+    let greeter = next_account_info(accounts_iter)?;
+    let record_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let outbox_account = next_account_info(accounts_iter)?;
+    let outbox_page_account = next_account_info(accounts_iter)?;
+    let profile_account = next_account_info(accounts_iter)?;
+    let treasury_account = next_account_info(accounts_iter)?;
+    check_writable(account)?;
+    check_writable(record_account)?;
+
+    if !greeter.is_signer {
+        msg!("Greeter must sign to say GM");
+        return Err(GmError::Unauthorized.into());
+    }
+
+    let mut greeting = GreetingAccount::unpack(&account.try_borrow_data()?)?;
+
+    let (record_address, bump_seed) =
+        find_greeter_record_address(program_id, account.key, greeter.key);
+    if *record_account.key != record_address {
+        msg!("Greeter record does not match the address derived from the greeting and greeter");
+        return Err(GmError::InvalidAccountAddress.into());
+    }
+
+    let clock = Clock::get()?;
+    let limits = config.settings.rate_limits;
+
+    // The record only exists once the greeter has said GM to this account
+    let mut record = if record_account.owner == program_id {
+        let record = GreeterRecord::unpack(&record_account.try_borrow_data()?)?;
+        limits.check_cooldown(record.last_gm_slot, clock.slot)?;
+        record
+    } else {
+        check_writable(greeter)?;
+        create_program_account(
+            program_id,
+            greeter,
+            record_account,
+            system_program,
+            GreeterRecord::LEN,
+            &[
+                GREETER_RECORD_SEED,
+                account.key.as_ref(),
+                greeter.key.as_ref(),
+                &[bump_seed],
+            ],
+        )?;
+        greeting.distinct_greeters = greeting
+            .distinct_greeters
+            .checked_add(1)
+            .ok_or(GmError::Overflow)?;
+        GreeterRecord {
+            greeting: *account.key,
+            greeter: *greeter.key,
+            gm_count: 0,
+            last_gm_slot: 0,
+        }
+    };
+
+    //Say GM in the Program output, escaping names migrated unchecked from legacy accounts
+    if is_legacy_only_name(&greeting.name) {
+        msg!("GM {:?}", greeting.name);
+    } else {
+        msg!("GM {}", greeting.name);
+    }
+
+    greeting.last_gm_unix_timestamp = clock.unix_timestamp;
+    greeting.last_gm_slot = clock.slot;
+    greeting.gm_count = greeting.gm_count.checked_add(1).ok_or(GmError::Overflow)?;
+    greeting.first_greeter.get_or_insert(*greeter.key);
+    greeting.history.push(HistoryEntry {
+        greeter: *greeter.key,
+        name_hash: hash(greeting.name.as_bytes()).to_bytes(),
+        unix_timestamp: clock.unix_timestamp,
+    });
+    record.gm_count = record.gm_count.checked_add(1).ok_or(GmError::Overflow)?;
+    record.last_gm_slot = clock.slot;
+
+    greeting.pack(&mut account.try_borrow_mut_data()?)?;
+    record.pack(&mut record_account.try_borrow_mut_data()?)?;
+
+    record_in_outbox(
+        program_id,
+        greeter,
+        outbox_account,
+        outbox_page_account,
+        system_program,
+        account.key,
+        &clock,
+        &limits,
+    )?;
+    record_in_streak(program_id, greeter, profile_account, system_program, &clock)?;
+
+    charge_fee(
+        program_id,
+        config,
+        greeter,
+        treasury_account,
+        system_program,
+        config.settings.fees.say_gm_lamports,
+    )
+}
+
+fn process_update(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    settings: &ConfigSettings,
+    name: String,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let account = next_greeting_account(program_id, accounts_iter)?;
+    let authority = next_account_info(accounts_iter)?;
+    check_writable(account)?;
+
+    let mut greeting = GreetingAccount::unpack(&account.try_borrow_data()?)?;
+    check_authority(authority, &greeting.authority)?;
+
+    check_name(&name, settings)?;
+    // Another name would leave the account off `find_greeting_address`, where `Initialize`
+    // could then create a second account for the new name. A name migrated from a legacy
+    // account that no settings accept can be replaced, as `Initialize` cannot derive from it.
+    if !is_legacy_only_name(&greeting.name)
+        && normalize_name(&name) != normalize_name(&greeting.name)
+    {
+        msg!("New name does not derive the address of the greeting account");
+        return Err(GmError::NameChangesAddress.into());
+    }
+    greeting.name = name;
+
+    greeting.pack(&mut account.try_borrow_mut_data()?)?;
+
+    Ok(())
+}
+
+fn process_migrate(program_id: &Pubkey, accounts: &[AccountInfo], seed: String) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let legacy_account = next_account_info(accounts_iter)?;
+    let account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+
+    if !payer.is_signer {
+        msg!("Payer must sign to migrate the greeting account");
+        return Err(GmError::Unauthorized.into());
+    }
+    check_writable(payer)?;
+    check_writable(legacy_account)?;
+
+    // Only the creator of the legacy account knows the seed it was created with
+    let legacy_address = Pubkey::create_with_seed(payer.key, &seed, program_id)?;
+    if *legacy_account.key != legacy_address {
+        msg!("Legacy greeting account was not created by the payer with this seed");
+        return Err(GmError::InvalidAccountAddress.into());
+    }
+
+    // A legacy account that has been moved no longer exists, and the new one holds its greeting.
+    // Any other greeting of the payer would hide a seed that never created a legacy account.
+    if legacy_account.owner != program_id {
+        if account.owner == program_id {
+            if let Ok(greeting) = GreetingAccount::unpack(&account.try_borrow_data()?) {
+                let (address, _) = find_greeting_address(program_id, payer.key, &greeting.name);
+                if greeting.authority == *payer.key && *account.key == address {
+                    msg!("Greeting account is already migrated");
+                    return Ok(());
+                }
+            }
+        }
+        msg!("Legacy greeting account does not have the correct program id");
+        return Err(GmError::IncorrectOwner.into());
+    }
+
+    // A legacy account that was rewritten in place already has a header
+    if GreetingAccount::unpack(&legacy_account.try_borrow_data()?).is_ok() {
+        msg!("Greeting account is already migrated");
+        return Ok(());
+    }
+
+    // The legacy program stored any name, so it is not checked against the current rules,
+    // which would leave the account stuck in the legacy layout
+    let LegacyGreeting { name } = LegacyGreeting::unpack(&legacy_account.try_borrow_data()?)?;
+    // The legacy layout did not record when the account was created nor who greeted it
+    let greeting = GreetingAccount::new(*payer.key, name, Clock::get()?.unix_timestamp, 0);
+
+    if legacy_account.data_len() >= greeting.required_space() {
+        msg!("Migrating greeting account in place");
+        return greeting.pack(&mut legacy_account.try_borrow_mut_data()?);
+    }
+
+    msg!("Moving greeting account to {}", account.key);
+    check_writable(account)?;
+    create_greeting_account(program_id, payer, account, system_program, greeting)?;
+    close_account(legacy_account, payer)
+}
+
+fn process_close(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let account = next_greeting_account(program_id, accounts_iter)?;
+    let authority = next_account_info(accounts_iter)?;
+    let destination = next_account_info(accounts_iter)?;
+    check_writable(account)?;
+    check_writable(destination)?;
+
+    let greeting = GreetingAccount::unpack(&account.try_borrow_data()?)?;
+    check_authority(authority, &greeting.authority)?;
+
+    if destination.key == account.key {
+        msg!("Lamports of a closed account cannot be sent to itself");
+        return Err(GmError::InvalidAccountAddress.into());
+    }
+
+    close_account(account, destination)
+}
+
+fn process_say_gm_to(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    config: &Config,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let sender = next_account_info(accounts_iter)?;
+    let recipient = next_account_info(accounts_iter)?;
+
+    say_gm_to(program_id, sender, recipient.key, accounts_iter, config)
+}
+
+fn process_say_gm_to_name(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    config: &Config,
+    name: String,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let sender = next_account_info(accounts_iter)?;
+    let record_account = next_account_info(accounts_iter)?;
+
+    let name = normalize_name(&name);
+    let (record_address, _) = find_name_record_address(program_id, &name);
+    if *record_account.key != record_address {
+        msg!("Name record does not match the address derived from the name");
+        return Err(GmError::InvalidAccountAddress.into());
+    }
+    if record_account.owner != program_id {
+        msg!("Name {} is not registered", name);
+        return Err(GmError::NameNotRegistered.into());
+    }
+    let record = NameRecord::unpack(&record_account.try_borrow_data()?)?;
+
+    say_gm_to(program_id, sender, &record.owner, accounts_iter, config)
+}
+
+/// Say GM to a recipient, taking the accounts following the sender and recipient
+/// in `SayGmTo`
+fn say_gm_to<'a, 'b>(
+    program_id: &Pubkey,
+    sender: &'a AccountInfo<'b>,
+    recipient: &Pubkey,
+    accounts_iter: &mut std::slice::Iter<'a, AccountInfo<'b>>,
+    config: &Config,
+) -> ProgramResult {
+    let inbox_account = next_account_info(accounts_iter)?;
+    let page_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let outbox_account = next_account_info(accounts_iter)?;
+    let outbox_page_account = next_account_info(accounts_iter)?;
+    let profile_account = next_account_info(accounts_iter)?;
+    let treasury_account = next_account_info(accounts_iter)?;
+    let record_account = next_account_info(accounts_iter)?;
+    check_writable(inbox_account)?;
+    check_writable(page_account)?;
+    check_writable(record_account)?;
+
+    if !sender.is_signer {
+        msg!("Sender must sign to say GM");
+        return Err(GmError::Unauthorized.into());
+    }
+
+    let (record_address, record_bump_seed) =
+        find_sender_record_address(program_id, recipient, sender.key);
+    if *record_account.key != record_address {
+        msg!("Sender record does not match the address derived from the recipient and sender");
+        return Err(GmError::InvalidAccountAddress.into());
+    }
+
+    let clock = Clock::get()?;
+    let limits = config.settings.rate_limits;
+
+    // The record only exists once the sender has said GM to this recipient
+    let mut record = if record_account.owner == program_id {
+        let record = SenderRecord::unpack(&record_account.try_borrow_data()?)?;
+        limits.check_cooldown(record.last_gm_slot, clock.slot)?;
+        record
+    } else {
+        check_writable(sender)?;
+        create_program_account(
+            program_id,
+            sender,
+            record_account,
+            system_program,
+            SenderRecord::LEN,
+            &[
+                SENDER_RECORD_SEED,
+                recipient.as_ref(),
+                sender.key.as_ref(),
+                &[record_bump_seed],
+            ],
+        )?;
+        SenderRecord {
+            recipient: *recipient,
+            sender: *sender.key,
+            gm_count: 0,
+            last_gm_slot: 0,
+        }
+    };
+
+    let (inbox_address, inbox_bump_seed) = find_inbox_address(program_id, recipient);
+    if *inbox_account.key != inbox_address {
+        msg!("Inbox does not match the address derived from the recipient");
+        return Err(GmError::InvalidAccountAddress.into());
+    }
+
+    // The inbox only exists once the recipient has been said GM to
+    let mut inbox = if inbox_account.owner == program_id {
+        InboxHeader::unpack(&inbox_account.try_borrow_data()?)?
+    } else {
+        check_writable(sender)?;
+        create_program_account(
+            program_id,
+            sender,
+            inbox_account,
+            system_program,
+            InboxHeader::LEN,
+            &[INBOX_SEED, recipient.as_ref(), &[inbox_bump_seed]],
+        )?;
+        InboxHeader {
+            recipient: *recipient,
+            gm_count: 0,
+            page_count: 0,
+        }
+    };
+
+    let index = inbox.current_page();
+    let (page_address, page_bump_seed) = find_inbox_page_address(program_id, recipient, index);
+    if *page_account.key != page_address {
+        msg!(
+            "Inbox page does not match the address of current page {}",
+            index
+        );
+        return Err(GmError::InvalidAccountAddress.into());
+    }
+
+    // The next page is started once the previous one is full
+    let mut page = if index < inbox.page_count {
+        InboxPage::unpack(&page_account.try_borrow_data()?)?
+    } else {
+        check_writable(sender)?;
+        create_program_account(
+            program_id,
+            sender,
+            page_account,
+            system_program,
+            InboxPage::LEN,
+            &[
+                INBOX_PAGE_SEED,
+                recipient.as_ref(),
+                &index.to_le_bytes(),
+                &[page_bump_seed],
+            ],
+        )?;
+        inbox.page_count = inbox.page_count.checked_add(1).ok_or(GmError::Overflow)?;
+        InboxPage {
+            recipient: *recipient,
+            index,
+            entries: Vec::with_capacity(InboxPage::CAPACITY),
+        }
+    };
+
+    //Say GM in the Program output
+    msg!("GM {} from {}", recipient, sender.key);
+
+    page.entries.push(InboxEntry {
+        sender: *sender.key,
+        unix_timestamp: clock.unix_timestamp,
+        slot: clock.slot,
+    });
+    inbox.gm_count = inbox.gm_count.checked_add(1).ok_or(GmError::Overflow)?;
+    record.gm_count = record.gm_count.checked_add(1).ok_or(GmError::Overflow)?;
+    record.last_gm_slot = clock.slot;
+
+    inbox.pack(&mut inbox_account.try_borrow_mut_data()?)?;
+    page.pack(&mut page_account.try_borrow_mut_data()?)?;
+    record.pack(&mut record_account.try_borrow_mut_data()?)?;
+
+    record_in_outbox(
+        program_id,
+        sender,
+        outbox_account,
+        outbox_page_account,
+        system_program,
+        recipient,
+        &clock,
+        &limits,
+    )?;
+    record_in_streak(program_id, sender, profile_account, system_program, &clock)?;
+
+    charge_fee(
+        program_id,
+        config,
+        sender,
+        treasury_account,
+        system_program,
+        config.settings.fees.say_gm_to_lamports,
+    )
+}
+
+fn process_initialize_config<'a>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo<'a>],
+    config_account: &AccountInfo<'a>,
+    settings: ConfigSettings,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let admin = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let program_data_account = next_account_info(accounts_iter)?;
+    let treasury_account = next_account_info(accounts_iter)?;
+
+    check_upgrade_authority(program_id, admin, program_data_account)?;
+    check_writable(admin)?;
+    check_writable(treasury_account)?;
+    check_writable(config_account)?;
+
+    settings.validate()?;
+
+    let (_, bump_seed) = find_config_address(program_id);
+    create_program_account(
+        program_id,
+        admin,
+        config_account,
+        system_program,
+        Config::LEN,
+        &[CONFIG_SEED, &[bump_seed]],
+    )?;
+
+    // The treasury holds no data, not even a header, only the fees paid to it on top of
+    // its rent
+    let (treasury_address, treasury_bump_seed) = find_treasury_address(program_id);
+    if *treasury_account.key != treasury_address {
+        msg!("Treasury does not match the address derived from the program");
+        return Err(GmError::InvalidAccountAddress.into());
+    }
+    create_program_account(
+        program_id,
+        admin,
+        treasury_account,
+        system_program,
+        0,
+        &[TREASURY_SEED, &[treasury_bump_seed]],
+    )?;
+
+    msg!("Config initialized with admin {}", admin.key);
+
+    let config = Config {
+        admin: *admin.key,
+        settings,
+        ..Config::uninitialized()
+    };
+    config.pack(&mut config_account.try_borrow_mut_data()?)
+}
+
+fn process_update_config(
+    accounts: &[AccountInfo],
+    config_account: &AccountInfo,
+    mut config: Config,
+    settings: ConfigSettings,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let admin = next_account_info(accounts_iter)?;
+    check_writable(config_account)?;
+
+    check_admin(admin, &config)?;
+    settings.validate()?;
+
+    if config.settings.timelock_slots == 0 {
+        config.settings = settings;
+        config.pending_settings = None;
+    } else {
+        let effective_slot = Clock::get()?
+            .slot
+            .saturating_add(config.settings.timelock_slots);
+        msg!("Settings take effect at slot {}", effective_slot);
+        config.pending_settings = Some(PendingSettings {
+            settings,
+            effective_slot,
+        });
+    }
+
+    config.pack(&mut config_account.try_borrow_mut_data()?)
+}
+
+fn process_propose_admin(
+    accounts: &[AccountInfo],
+    config_account: &AccountInfo,
+    mut config: Config,
+    new_admin: Pubkey,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let admin = next_account_info(accounts_iter)?;
+    check_writable(config_account)?;
+
+    check_admin(admin, &config)?;
+
+    let effective_slot = Clock::get()?
+        .slot
+        .saturating_add(config.settings.timelock_slots);
+    msg!(
+        "Admin {} proposed, can accept from slot {}",
+        new_admin,
+        effective_slot
+    );
+    config.pending_admin = Some(PendingAdmin {
+        admin: new_admin,
+        effective_slot,
+    });
+
+    config.pack(&mut config_account.try_borrow_mut_data()?)
+}
+
+fn process_accept_admin(
+    accounts: &[AccountInfo],
+    config_account: &AccountInfo,
+    mut config: Config,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let new_admin = next_account_info(accounts_iter)?;
+    check_writable(config_account)?;
+
+    let pending = match config.pending_admin {
+        Some(pending) if pending.admin == *new_admin.key && new_admin.is_signer => pending,
+        _ => {
+            msg!("Signer is not the proposed admin");
+            return Err(GmError::NoAdminProposal.into());
+        }
+    };
+    let slot = Clock::get()?.slot;
+    if slot < pending.effective_slot {
+        msg!(
+            "Admin can accept in {} slots",
+            pending.effective_slot - slot
+        );
+        return Err(GmError::TimelockNotElapsed.into());
+    }
+
+    msg!("Admin changed to {}", new_admin.key);
+    config.admin = pending.admin;
+    config.pending_admin = None;
+
+    config.pack(&mut config_account.try_borrow_mut_data()?)
+}
+
+fn process_cancel_proposals(
+    accounts: &[AccountInfo],
+    config_account: &AccountInfo,
+    mut config: Config,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let admin = next_account_info(accounts_iter)?;
+    check_writable(config_account)?;
+
+    check_admin(admin, &config)?;
+
+    config.pending_settings = None;
+    config.pending_admin = None;
+    config.pending_fee_exempt = None;
+
+    config.pack(&mut config_account.try_borrow_mut_data()?)
+}
+
+fn process_set_paused_instructions(
+    accounts: &[AccountInfo],
+    config_account: &AccountInfo,
+    mut config: Config,
+    paused_instructions: u64,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let admin = next_account_info(accounts_iter)?;
+    check_writable(config_account)?;
+
+    check_admin(admin, &config)?;
+
+    msg!("Paused instructions set to {:#x}", paused_instructions);
+    config.paused_instructions = paused_instructions;
+
+    config.pack(&mut config_account.try_borrow_mut_data()?)
+}
+
+fn process_set_fee_exempt(
+    accounts: &[AccountInfo],
+    config_account: &AccountInfo,
+    mut config: Config,
+    wallet: Pubkey,
+    exempt: bool,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let admin = next_account_info(accounts_iter)?;
+    check_writable(config_account)?;
+
+    check_admin(admin, &config)?;
+
+    // Changes build on the list proposed before, so several can wait for the same timelock
+    let mut fee_exempt = match config.pending_fee_exempt.take() {
+        Some(pending) => pending.fee_exempt,
+        None => config.fee_exempt.clone(),
+    };
+    if !exempt {
+        fee_exempt.retain(|exempt_wallet| *exempt_wallet != wallet);
+    } else if !fee_exempt.contains(&wallet) {
+        if fee_exempt.len() >= Config::MAX_FEE_EXEMPT {
+            msg!(
+                "Fee exempt list already holds {} wallets",
+                Config::MAX_FEE_EXEMPT
+            );
+            return Err(GmError::FeeExemptListFull.into());
+        }
+        fee_exempt.push(wallet);
+    }
+
+    if config.settings.timelock_slots == 0 {
+        config.fee_exempt = fee_exempt;
+    } else {
+        let effective_slot = Clock::get()?
+            .slot
+            .saturating_add(config.settings.timelock_slots);
+        msg!("Fee exempt list takes effect at slot {}", effective_slot);
+        config.pending_fee_exempt = Some(PendingFeeExempt {
+            fee_exempt,
+            effective_slot,
+        });
+    }
+
+    config.pack(&mut config_account.try_borrow_mut_data()?)
+}
+
+fn process_withdraw_treasury(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    config: &Config,
+    lamports: u64,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let admin = next_account_info(accounts_iter)?;
+    let treasury_account = next_account_info(accounts_iter)?;
+    let destination = next_account_info(accounts_iter)?;
+    check_writable(treasury_account)?;
+    check_writable(destination)?;
+
+    check_admin(admin, config)?;
+    check_treasury(program_id, treasury_account)?;
+    if treasury_account.owner != program_id {
+        msg!("Treasury is not initialized");
+        return Err(GmError::IncorrectOwner.into());
+    }
+
+    let available = treasury_account
+        .lamports()
+        .saturating_sub(Rent::get()?.minimum_balance(treasury_account.data_len()));
+    if lamports > available {
+        msg!("Treasury holds {} lamports above its rent", available);
+        return Err(GmError::InsufficientFunds.into());
+    }
+
+    **treasury_account.try_borrow_mut_lamports()? -= lamports;
+    **destination.try_borrow_mut_lamports()? = destination
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(GmError::Overflow)?;
+
+    Ok(())
+}
+
+/// Transfer the fee of a GM from the sender to the treasury, unless the sender is exempt
+fn charge_fee<'a>(
+    program_id: &Pubkey,
+    config: &Config,
+    sender: &AccountInfo<'a>,
+    treasury_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    lamports: u64,
+) -> ProgramResult {
+    if lamports == 0 || config.is_fee_exempt(sender.key) {
+        return Ok(());
+    }
+
+    check_treasury(program_id, treasury_account)?;
+    check_writable(sender)?;
+    check_writable(treasury_account)?;
+
+    msg!("Charging a fee of {} lamports", lamports);
+    invoke(
+        &system_instruction::transfer(sender.key, treasury_account.key, lamports),
+        &[
+            sender.clone(),
+            treasury_account.clone(),
+            system_program.clone(),
+        ],
+    )
+}
+
+fn process_set_utc_offset(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    utc_offset_minutes: i16,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let wallet = next_account_info(accounts_iter)?;
+    let profile_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+
+    if !wallet.is_signer {
+        msg!("Wallet must sign to change its profile");
+        return Err(GmError::Unauthorized.into());
+    }
+
+    check_utc_offset(utc_offset_minutes)?;
+
+    let mut profile = load_or_create_profile(program_id, wallet, profile_account, system_program)?;
+    profile.set_utc_offset(utc_offset_minutes, Clock::get()?.unix_timestamp)?;
+
+    profile.pack(&mut profile_account.try_borrow_mut_data()?)
+}
+
+fn process_create_profile(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    display_name: String,
+    avatar: Option<Avatar>,
+    bio: String,
+    utc_offset_minutes: i16,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let wallet = next_account_info(accounts_iter)?;
+    let profile_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+
+    if !wallet.is_signer {
+        msg!("Wallet must sign to create its profile");
+        return Err(GmError::Unauthorized.into());
+    }
+    check_writable(wallet)?;
+    check_writable(profile_account)?;
+
+    check_profile_details(&display_name, avatar.as_ref(), &bio, utc_offset_minutes)?;
+
+    create_profile(program_id, wallet, profile_account, system_program)?;
+
+    let profile = Profile {
+        utc_offset_minutes,
+        display_name,
+        avatar,
+        bio,
+        ..Profile::new(*wallet.key)
+    };
+    profile.pack(&mut profile_account.try_borrow_mut_data()?)
+}
+
+fn process_update_profile(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    display_name: String,
+    avatar: Option<Avatar>,
+    bio: String,
+    utc_offset_minutes: i16,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let wallet = next_account_info(accounts_iter)?;
+    let profile_account = next_account_info(accounts_iter)?;
+    check_writable(profile_account)?;
+
+    let mut profile = unpack_own_profile(program_id, wallet, profile_account)?;
+
+    check_profile_details(&display_name, avatar.as_ref(), &bio, utc_offset_minutes)?;
+
+    profile.set_utc_offset(utc_offset_minutes, Clock::get()?.unix_timestamp)?;
+    profile.display_name = display_name;
+    profile.avatar = avatar;
+    profile.bio = bio;
+
+    profile.pack(&mut profile_account.try_borrow_mut_data()?)
+}
+
+fn process_close_profile(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let wallet = next_account_info(accounts_iter)?;
+    let profile_account = next_account_info(accounts_iter)?;
+    let destination = next_account_info(accounts_iter)?;
+    check_writable(profile_account)?;
+    check_writable(destination)?;
+
+    unpack_own_profile(program_id, wallet, profile_account)?;
+
+    close_account(profile_account, destination)
+}
+
+fn process_register_name(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    settings: &ConfigSettings,
+    name: String,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let owner = next_account_info(accounts_iter)?;
+    let record_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let reverse_account = next_account_info(accounts_iter)?;
+
+    if !owner.is_signer {
+        msg!("Owner must sign to register a name");
+        return Err(GmError::Unauthorized.into());
+    }
+    check_writable(owner)?;
+    check_writable(record_account)?;
+    check_writable(reverse_account)?;
+
+    let name = normalize_name(&name);
+    check_name(&name, settings)?;
+
+    let (record_address, bump_seed) = find_name_record_address(program_id, &name);
+    if *record_account.key != record_address {
+        msg!("Name record does not match the address derived from the name");
+        return Err(GmError::InvalidAccountAddress.into());
+    }
+    if record_account.owner == program_id {
+        msg!("Name {} is already registered", name);
+        return Err(GmError::NameAlreadyRegistered.into());
+    }
+
+    create_program_account(
+        program_id,
+        owner,
+        record_account,
+        system_program,
+        NameRecord::LEN,
+        &[NAME_RECORD_SEED, &name_record_seed(&name), &[bump_seed]],
+    )?;
+
+    msg!("Name {} registered to {}", name, owner.key);
+
+    let record = NameRecord {
+        owner: *owner.key,
+        registered_at: Clock::get()?.unix_timestamp,
+        name,
+    };
+    record.pack(&mut record_account.try_borrow_mut_data()?)?;
+
+    // The first name registered by a wallet becomes its primary name
+    check_reverse_record_address(program_id, owner.key, reverse_account)?;
+    if reverse_account.owner != program_id {
+        set_reverse_record(
+            program_id,
+            owner,
+            reverse_account,
+            system_program,
+            record.name,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn process_transfer_name(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    new_owner: Pubkey,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let owner = next_account_info(accounts_iter)?;
+    let record_account = next_account_info(accounts_iter)?;
+    let reverse_account = next_account_info(accounts_iter)?;
+    check_writable(record_account)?;
+
+    let mut record = unpack_own_name_record(program_id, owner, record_account)?;
+
+    clear_reverse_record(program_id, owner.key, reverse_account, &record.name, owner)?;
+
+    msg!("Name {} transferred to {}", record.name, new_owner);
+    record.owner = new_owner;
+
+    record.pack(&mut record_account.try_borrow_mut_data()?)
+}
+
+fn process_release_name(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let owner = next_account_info(accounts_iter)?;
+    let record_account = next_account_info(accounts_iter)?;
+    let destination = next_account_info(accounts_iter)?;
+    let reverse_account = next_account_info(accounts_iter)?;
+    check_writable(record_account)?;
+    check_writable(destination)?;
+
+    let record = unpack_own_name_record(program_id, owner, record_account)?;
+    msg!("Name {} released", record.name);
+
+    clear_reverse_record(
+        program_id,
+        owner.key,
+        reverse_account,
+        &record.name,
+        destination,
+    )?;
+    close_account(record_account, destination)
+}
+
+fn process_set_primary_name(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let wallet = next_account_info(accounts_iter)?;
+    let record_account = next_account_info(accounts_iter)?;
+    let reverse_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    check_writable(reverse_account)?;
+
+    let record = unpack_own_name_record(program_id, wallet, record_account)?;
+    msg!("Primary name of {} set to {}", wallet.key, record.name);
+
+    check_reverse_record_address(program_id, wallet.key, reverse_account)?;
+    set_reverse_record(
+        program_id,
+        wallet,
+        reverse_account,
+        system_program,
+        record.name,
+    )
+}
+
+/// Point the reverse record of the wallet to `name`, creating it funded by the wallet
+/// if needed. The address of the reverse record must have been checked.
+fn set_reverse_record<'a>(
+    program_id: &Pubkey,
+    wallet: &AccountInfo<'a>,
+    reverse_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    name: String,
+) -> ProgramResult {
+    if reverse_account.owner != program_id {
+        check_writable(wallet)?;
+        let (_, bump_seed) = find_reverse_record_address(program_id, wallet.key);
+        create_program_account(
+            program_id,
+            wallet,
+            reverse_account,
+            system_program,
+            ReverseRecord::LEN,
+            &[REVERSE_RECORD_SEED, wallet.key.as_ref(), &[bump_seed]],
+        )?;
+    }
+
+    let reverse = ReverseRecord {
+        wallet: *wallet.key,
+        name,
+    };
+    reverse.pack(&mut reverse_account.try_borrow_mut_data()?)
+}
+
+/// Close the reverse record of the wallet if it points to `name`, which the wallet is
+/// giving up, so that reverse records only ever point to names of their wallet
+fn clear_reverse_record(
+    program_id: &Pubkey,
+    wallet: &Pubkey,
+    reverse_account: &AccountInfo,
+    name: &str,
+    destination: &AccountInfo,
+) -> ProgramResult {
+    check_reverse_record_address(program_id, wallet, reverse_account)?;
+    if reverse_account.owner != program_id {
+        return Ok(());
+    }
+
+    let reverse = ReverseRecord::unpack(&reverse_account.try_borrow_data()?)?;
+    if reverse.name != name {
+        return Ok(());
+    }
+
+    check_writable(reverse_account)?;
+    check_writable(destination)?;
+    close_account(reverse_account, destination)
+}
+
+/// Check that the reverse record is at the address derived from the wallet
+fn check_reverse_record_address(
+    program_id: &Pubkey,
+    wallet: &Pubkey,
+    reverse_account: &AccountInfo,
+) -> ProgramResult {
+    let (reverse_address, _) = find_reverse_record_address(program_id, wallet);
+    if *reverse_account.key != reverse_address {
+        msg!("Reverse record does not match the address derived from the wallet");
+        return Err(GmError::InvalidAccountAddress.into());
+    }
+
+    Ok(())
+}
+
+/// Read a name record the program owns, checking that the owner of the name signed
+fn unpack_own_name_record(
+    program_id: &Pubkey,
+    owner: &AccountInfo,
+    record_account: &AccountInfo,
+) -> Result<NameRecord, ProgramError> {
+    if record_account.owner != program_id {
+        msg!("Name record does not have the correct program id");
+        return Err(GmError::IncorrectOwner.into());
+    }
+
+    let record = NameRecord::unpack(&record_account.try_borrow_data()?)?;
+    if record.owner != *owner.key || !owner.is_signer {
+        msg!("Owner of the name must sign to change it");
+        return Err(GmError::Unauthorized.into());
+    }
+
+    Ok(record)
+}
+
+/// Count a GM towards the streak of the sender, on the current day in its time zone
+fn record_in_streak<'a>(
+    program_id: &Pubkey,
+    sender: &AccountInfo<'a>,
+    profile_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    clock: &Clock,
+) -> ProgramResult {
+    let mut profile = load_or_create_profile(program_id, sender, profile_account, system_program)?;
+
+    let day = profile.local_day(clock.unix_timestamp);
+    profile.streak.record(day)?;
+    msg!(
+        "GM streak of {} days, longest {}",
+        profile.streak.current,
+        profile.streak.longest
+    );
+
+    profile.pack(&mut profile_account.try_borrow_mut_data()?)
+}
+
+/// Read the profile of the wallet, creating it funded by the wallet if it does not exist yet
+fn load_or_create_profile<'a>(
+    program_id: &Pubkey,
+    wallet: &AccountInfo<'a>,
+    profile_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+) -> Result<Profile, ProgramError> {
+    check_writable(profile_account)?;
+
+    if profile_account.owner == program_id {
+        let profile = Profile::unpack(&profile_account.try_borrow_data()?)?;
+        if profile.wallet != *wallet.key {
+            msg!("Profile does not belong to the wallet");
+            return Err(GmError::InvalidAccountAddress.into());
+        }
+        return Ok(profile);
+    }
+
+    check_writable(wallet)?;
+    create_profile(program_id, wallet, profile_account, system_program)?;
+
+    Ok(Profile::new(*wallet.key))
+}
+
+/// Create the profile account of the wallet at its derived address, funded by the wallet
+fn create_profile<'a>(
+    program_id: &Pubkey,
+    wallet: &AccountInfo<'a>,
+    profile_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+) -> ProgramResult {
+    let (profile_address, bump_seed) = find_profile_address(program_id, wallet.key);
+    if *profile_account.key != profile_address {
+        msg!("Profile does not match the address derived from the wallet");
+        return Err(GmError::InvalidAccountAddress.into());
+    }
+
+    create_program_account(
+        program_id,
+        wallet,
+        profile_account,
+        system_program,
+        Profile::LEN,
+        &[PROFILE_SEED, wallet.key.as_ref(), &[bump_seed]],
+    )
+}
+
+/// Read a profile the program owns, checking that the wallet owning it signed
+fn unpack_own_profile(
+    program_id: &Pubkey,
+    wallet: &AccountInfo,
+    profile_account: &AccountInfo,
+) -> Result<Profile, ProgramError> {
+    if profile_account.owner != program_id {
+        msg!("Profile does not have the correct program id");
+        return Err(GmError::IncorrectOwner.into());
+    }
+
+    let profile = Profile::unpack(&profile_account.try_borrow_data()?)?;
+    if profile.wallet != *wallet.key || !wallet.is_signer {
+        msg!("Wallet of the profile must sign to change it");
+        return Err(GmError::Unauthorized.into());
+    }
+
+    Ok(profile)
+}
+
+/// Record a GM in the current page of the outbox of the sender, creating the outbox on the
+/// sender's first GM and each page once the previous one is full, and take it from the
+/// sender's quota
+#[allow(clippy::too_many_arguments)]
+fn record_in_outbox<'a>(
+    program_id: &Pubkey,
+    sender: &AccountInfo<'a>,
+    outbox_account: &AccountInfo<'a>,
+    page_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    recipient: &Pubkey,
+    clock: &Clock,
+    limits: &RateLimits,
+) -> ProgramResult {
+    check_writable(outbox_account)?;
+    check_writable(page_account)?;
+
+    let (outbox_address, bump_seed) = find_outbox_address(program_id, sender.key);
+    if *outbox_account.key != outbox_address {
+        msg!("Outbox does not match the address derived from the sender");
+        return Err(GmError::InvalidAccountAddress.into());
+    }
+
+    let mut outbox = if outbox_account.owner == program_id {
+        Outbox::unpack(&outbox_account.try_borrow_data()?)?
+    } else {
+        check_writable(sender)?;
+        create_program_account(
+            program_id,
+            sender,
+            outbox_account,
+            system_program,
+            Outbox::LEN,
+            &[OUTBOX_SEED, sender.key.as_ref(), &[bump_seed]],
+        )?;
+        Outbox::new(*sender.key, TokenBucket::new(limits, clock.slot))
+    };
+
+    outbox.quota.take(limits, clock.slot)?;
+
+    let index = outbox.current_page();
+    let (page_address, page_bump_seed) = find_outbox_page_address(program_id, sender.key, index);
+    if *page_account.key != page_address {
+        msg!(
+            "Outbox page does not match the address of current page {}",
+            index
+        );
+        return Err(GmError::InvalidAccountAddress.into());
+    }
+
+    // The next page is started once the previous one is full
+    let mut page = if index < outbox.page_count {
+        OutboxPage::unpack(&page_account.try_borrow_data()?)?
+    } else {
+        check_writable(sender)?;
+        create_program_account(
+            program_id,
+            sender,
+            page_account,
+            system_program,
+            OutboxPage::LEN,
+            &[
+                OUTBOX_PAGE_SEED,
+                sender.key.as_ref(),
+                &index.to_le_bytes(),
+                &[page_bump_seed],
+            ],
+        )?;
+        outbox.page_count = outbox.page_count.checked_add(1).ok_or(GmError::Overflow)?;
+        OutboxPage {
+            sender: *sender.key,
+            index,
+            entries: Vec::with_capacity(OutboxPage::CAPACITY),
+        }
+    };
+
+    page.entries.push(OutboxEntry {
+        recipient: *recipient,
+        unix_timestamp: clock.unix_timestamp,
+    });
+    outbox.gm_count = outbox.gm_count.checked_add(1).ok_or(GmError::Overflow)?;
+
+    outbox.pack(&mut outbox_account.try_borrow_mut_data()?)?;
+    page.pack(&mut page_account.try_borrow_mut_data()?)
+}
+
+/// Create the greeting account at the address derived from the payer and name, sized for
+/// the longest name and its full history so later updates always fit, and store the
+/// greeting in it
+fn create_greeting_account<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    greeting: GreetingAccount,
+) -> ProgramResult {
+    let (address, bump_seed) = find_greeting_address(program_id, payer.key, &greeting.name);
+    if *account.key != address {
+        msg!("Greeting account does not match the address derived from the payer and name");
+        return Err(GmError::InvalidAccountAddress.into());
+    }
+
+    create_program_account(
+        program_id,
+        payer,
+        account,
+        system_program,
+        greeting.required_space(),
+        &[
+            GREETING_SEED,
+            payer.key.as_ref(),
+            &greeting_name_seed(&greeting.name),
+            &[bump_seed],
+        ],
+    )?;
+
+    greeting.pack(&mut account.try_borrow_mut_data()?)
+}
+
+/// Create a program owned account of `space` bytes at the address derived from `seeds`,
+/// funded by the payer to be rent exempt
+fn create_program_account<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    space: usize,
+    seeds: &[&[u8]],
+) -> ProgramResult {
+    if account.owner == program_id {
+        msg!("Account {} is already initialized", account.key);
+        return Err(GmError::AlreadyInitialized.into());
+    }
+
+    let lamports = Rent::get()?.minimum_balance(space);
+
+    if account.lamports() == 0 {
+        return invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                account.key,
+                lamports,
+                space as u64,
+                program_id,
+            ),
+            &[payer.clone(), account.clone(), system_program.clone()],
+            &[seeds],
+        );
+    }
+
+    // Anyone can send lamports to a derived address before we create the account, which
+    // `create_account` refuses, so the account is topped up and taken over step by step
+    let top_up = lamports.saturating_sub(account.lamports());
+    if top_up > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, top_up),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+    invoke_signed(
+        &system_instruction::allocate(account.key, space as u64),
+        &[account.clone(), system_program.clone()],
+        &[seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(account.key, program_id),
+        &[account.clone(), system_program.clone()],
+        &[seeds],
+    )
+}
+
+/// Move all lamports of a program account to the destination and mark its data closed,
+/// leaving the runtime to remove it at the end of the transaction
+fn close_account(account: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+    let lamports = account.lamports();
+    **destination.try_borrow_mut_lamports()? = destination
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(GmError::Overflow)?;
+    **account.try_borrow_mut_lamports()? = 0;
+
+    let mut data = account.try_borrow_mut_data()?;
+    data.fill(0);
+    // Legacy accounts can be smaller than a discriminator, any prefix of it still fails to decode
+    let len = data.len().min(CLOSED_ACCOUNT_DISCRIMINATOR.len());
+    data[..len].copy_from_slice(&CLOSED_ACCOUNT_DISCRIMINATOR[..len]);
+
+    Ok(())
+}
+
+/// Check that the account was passed as writable, so the runtime will keep our changes
+fn check_writable(account: &AccountInfo) -> ProgramResult {
+    if !account.is_writable {
+        msg!("Account {} must be writable", account.key);
+        return Err(GmError::AccountNotWritable.into());
+    }
+
+    Ok(())
+}
+
+/// Check that the expected authority signed the instruction
+fn check_authority(authority: &AccountInfo, expected: &Pubkey) -> ProgramResult {
+    if authority.key != expected {
+        msg!("Signer is not the authority of the greeting account");
+        return Err(GmError::Unauthorized.into());
+    }
+    if !authority.is_signer {
+        msg!("Authority must sign to change the greeting account");
+        return Err(GmError::Unauthorized.into());
+    }
+
+    Ok(())
+}
+
+/// Check that the upgrade authority of the program, as recorded by the upgradeable loader
+/// in the program data account, signed the instruction
+fn check_upgrade_authority(
+    program_id: &Pubkey,
+    authority: &AccountInfo,
+    program_data_account: &AccountInfo,
+) -> ProgramResult {
+    let (program_data_address, _) =
+        Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+    if *program_data_account.key != program_data_address {
+        msg!("Program data account does not match the address derived from the program");
+        return Err(GmError::InvalidAccountAddress.into());
+    }
+    if *program_data_account.owner != bpf_loader_upgradeable::id() {
+        msg!("Program data account is not owned by the upgradeable loader");
+        return Err(GmError::IncorrectOwner.into());
+    }
+
+    // The program bytes follow the state, which is all we read
+    let upgrade_authority = match bincode::deserialize(&program_data_account.try_borrow_data()?) {
+        Ok(UpgradeableLoaderState::ProgramData {
+            upgrade_authority_address,
+            ..
+        }) => upgrade_authority_address,
+        _ => {
+            msg!("Program data account does not hold program data");
+            return Err(GmError::InvalidAccountData.into());
+        }
+    };
+
+    if upgrade_authority != Some(*authority.key) || !authority.is_signer {
+        msg!("Upgrade authority of the program must sign");
+        return Err(GmError::Unauthorized.into());
+    }
+
+    Ok(())
+}
+
+/// Check that the treasury is at its derived address
+fn check_treasury(program_id: &Pubkey, treasury_account: &AccountInfo) -> ProgramResult {
+    let (treasury_address, _) = find_treasury_address(program_id);
+    if *treasury_account.key != treasury_address {
+        msg!("Treasury does not match the address derived from the program");
+        return Err(GmError::InvalidAccountAddress.into());
+    }
+
+    Ok(())
+}
+
+/// Check that the admin of the config signed the instruction
+fn check_admin(admin: &AccountInfo, config: &Config) -> ProgramResult {
+    if *admin.key != config.admin || !admin.is_signer {
+        msg!("Admin of the config must sign to change it");
+        return Err(GmError::Unauthorized.into());
+    }
+
+    Ok(())
+}
+
+/// Read the config at its derived address, using the default settings until it is
+/// initialized. An uninitialized config has no admin, so nobody can update it.
+fn load_config(program_id: &Pubkey, config_account: &AccountInfo) -> Result<Config, ProgramError> {
+    let (config_address, _) = find_config_address(program_id);
+    if *config_account.key != config_address {
+        msg!("Config does not match the address derived from the program");
+        return Err(GmError::InvalidAccountAddress.into());
+    }
+
+    if config_account.owner != program_id {
+        return Ok(Config::uninitialized());
+    }
+
+    let mut config = Config::unpack(&config_account.try_borrow_data()?)?;
+    // Settings past their timelock are in effect even before the config is next written
+    config.apply_pending_changes(Clock::get()?.slot);
+
+    Ok(config)
+}
+
+/// Check that a name follows the naming rules before it is stored or logged
+fn check_name(name: &str, settings: &ConfigSettings) -> ProgramResult {
+    settings.name_rules().validate(name)?;
+
+    Ok(())
+}
+
+/// Whether a stored name breaks the naming rules under any settings, which only names
+/// migrated from legacy accounts can
+fn is_legacy_only_name(name: &str) -> bool {
+    NameRules::default().validate(name).is_err()
+}
+
+/// Check that the UTC offset is the one of a time zone in use
+fn check_utc_offset(utc_offset_minutes: i16) -> ProgramResult {
+    if !(Profile::MIN_UTC_OFFSET_MINUTES..=Profile::MAX_UTC_OFFSET_MINUTES)
+        .contains(&utc_offset_minutes)
+    {
+        msg!(
+            "UTC offset of {} minutes is out of range",
+            utc_offset_minutes
+        );
+        return Err(GmError::InvalidUtcOffset.into());
+    }
+
+    Ok(())
+}
+
+/// Check the details shown for a wallet before they are stored in its profile.
+/// Display names and bios may join emoji, unlike names used in addresses.
+fn check_profile_details(
+    display_name: &str,
+    avatar: Option<&Avatar>,
+    bio: &str,
+    utc_offset_minutes: i16,
+) -> ProgramResult {
+    let display_name_rules = NameRules {
+        max_len: Profile::MAX_DISPLAY_NAME_LEN,
+        allow_zero_width_joiner: true,
+    };
+    // The display name is empty until set, and can be cleared
+    if !display_name.is_empty() {
+        if let Err(e) = display_name_rules.validate(display_name) {
+            msg!("Display name is invalid");
+            return Err(e.into());
+        }
+    }
+
+    if let Some(avatar) = avatar {
+        validate_avatar_uri(&avatar.uri)?;
+    }
+
+    let bio_rules = NameRules {
+        max_len: Profile::MAX_BIO_LEN,
+        allow_zero_width_joiner: true,
+    };
+    // The bio is optional
+    if !bio.is_empty() {
+        if let Err(e) = bio_rules.validate(bio) {
+            msg!("Bio is invalid");
+            return Err(e.into());
+        }
+    }
+
+    check_utc_offset(utc_offset_minutes)
+}
+
+/// Get the next account, which must be a greeting account owned by the program
+fn next_greeting_account<'a, 'b>(
+    program_id: &Pubkey,
+    accounts_iter: &mut std::slice::Iter<'a, AccountInfo<'b>>,
+) -> Result<&'a AccountInfo<'b>, ProgramError> {
+    let account = next_account_info(accounts_iter)?;
+
+    // The account must be owned by the program in order to modify its data
+    if account.owner != program_id {
+        msg!("Greeted account does not have the correct program id");
+        return Err(GmError::IncorrectOwner.into());
+    }
+
+    Ok(account)
+}
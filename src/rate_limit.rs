@@ -0,0 +1,202 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{clock::Slot, msg};
+
+use crate::error::GmError;
+
+/// Limits on how often GMs can be said, measured in slots
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub struct RateLimits {
+    /// Slots a greeter must wait between two GMs to the same greeting account or recipient
+    pub cooldown_slots: u64,
+    /// GMs a greeter can say in a burst, to any recipient
+    pub quota_capacity: u64,
+    /// Slots it takes to earn back one GM of the quota
+    pub quota_refill_slots: u64,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        // About a minute between GMs to the same account, and one GM a minute overall
+        // after a burst of ten
+        Self {
+            cooldown_slots: 150,
+            quota_capacity: 10,
+            quota_refill_slots: 150,
+        }
+    }
+}
+
+impl RateLimits {
+    pub const LEN: usize = 8 + 8 + 8;
+
+    /// Check that the cooldown since the last GM to the same account or recipient has passed
+    pub fn check_cooldown(&self, last_gm_slot: Slot, slot: Slot) -> Result<(), GmError> {
+        let ready_at = last_gm_slot.saturating_add(self.cooldown_slots);
+        if slot < ready_at {
+            msg!("Cooldown active for another {} slots", ready_at - slot);
+            return Err(GmError::RateLimited);
+        }
+
+        Ok(())
+    }
+}
+
+/// Token bucket of the GMs a greeter can still say, refilled as slots pass
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
+pub struct TokenBucket {
+    pub tokens: u64,
+    /// Slot up to which refills have been accounted for
+    pub last_refill_slot: Slot,
+}
+
+impl TokenBucket {
+    pub const LEN: usize = 8 + 8;
+
+    /// A full bucket
+    pub fn new(limits: &RateLimits, slot: Slot) -> Self {
+        Self {
+            tokens: limits.quota_capacity,
+            last_refill_slot: slot,
+        }
+    }
+
+    /// Refill the bucket for the slots passed since the last refill and take one token
+    pub fn take(&mut self, limits: &RateLimits, slot: Slot) -> Result<(), GmError> {
+        let elapsed = slot.saturating_sub(self.last_refill_slot);
+        let earned = elapsed
+            .checked_div(limits.quota_refill_slots)
+            .unwrap_or(u64::MAX);
+        if earned > 0 {
+            self.tokens = self.tokens.saturating_add(earned);
+            // Keep the slots towards the next token, unless the bucket is full
+            self.last_refill_slot = if self.tokens >= limits.quota_capacity {
+                slot
+            } else {
+                self.last_refill_slot + earned * limits.quota_refill_slots
+            };
+        }
+        // Clamped on every GM so that a capacity lowered by the admin also applies to
+        // tokens earned before
+        self.tokens = self.tokens.min(limits.quota_capacity);
+
+        if self.tokens == 0 {
+            msg!(
+                "GM quota exhausted, one GM is earned back every {} slots",
+                limits.quota_refill_slots
+            );
+            return Err(GmError::RateLimited);
+        }
+        self.tokens -= 1;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: RateLimits = RateLimits {
+        cooldown_slots: 150,
+        quota_capacity: 3,
+        quota_refill_slots: 100,
+    };
+
+    #[test]
+    fn cooldown_ends_on_its_last_slot() {
+        assert_eq!(
+            LIMITS.check_cooldown(1_000, 1_149),
+            Err(GmError::RateLimited)
+        );
+        assert_eq!(LIMITS.check_cooldown(1_000, 1_150), Ok(()));
+        assert_eq!(LIMITS.check_cooldown(1_000, 2_000), Ok(()));
+    }
+
+    #[test]
+    fn cooldown_does_not_overflow() {
+        assert_eq!(
+            LIMITS.check_cooldown(u64::MAX, u64::MAX - 1),
+            Err(GmError::RateLimited)
+        );
+    }
+
+    #[test]
+    fn take_fails_once_the_bucket_is_empty() {
+        let mut bucket = TokenBucket::new(&LIMITS, 1_000);
+        for _ in 0..LIMITS.quota_capacity {
+            bucket.take(&LIMITS, 1_000).unwrap();
+        }
+        assert_eq!(bucket.tokens, 0);
+        assert_eq!(bucket.take(&LIMITS, 1_099), Err(GmError::RateLimited));
+        assert_eq!(bucket.tokens, 0);
+    }
+
+    #[test]
+    fn partial_refill_keeps_the_slots_towards_the_next_token() {
+        let mut bucket = TokenBucket {
+            tokens: 0,
+            last_refill_slot: 1_000,
+        };
+        // One token earned, with 50 slots towards the next
+        bucket.take(&LIMITS, 1_150).unwrap();
+        assert_eq!(
+            bucket,
+            TokenBucket {
+                tokens: 0,
+                last_refill_slot: 1_100,
+            }
+        );
+        // The remainder counts towards the next token
+        bucket.take(&LIMITS, 1_200).unwrap();
+        assert_eq!(
+            bucket,
+            TokenBucket {
+                tokens: 0,
+                last_refill_slot: 1_200,
+            }
+        );
+    }
+
+    #[test]
+    fn refill_stops_at_capacity() {
+        let mut bucket = TokenBucket {
+            tokens: 1,
+            last_refill_slot: 1_000,
+        };
+        bucket.take(&LIMITS, 10_050).unwrap();
+        assert_eq!(
+            bucket,
+            TokenBucket {
+                tokens: LIMITS.quota_capacity - 1,
+                last_refill_slot: 10_050,
+            }
+        );
+    }
+
+    #[test]
+    fn lowered_capacity_applies_before_any_refill() {
+        let mut bucket = TokenBucket {
+            tokens: 10,
+            last_refill_slot: 1_000,
+        };
+        // No slot passed, so no token is earned
+        bucket.take(&LIMITS, 1_000).unwrap();
+        assert_eq!(bucket.tokens, LIMITS.quota_capacity - 1);
+    }
+
+    #[test]
+    fn zero_refill_slots_refill_the_bucket_at_once() {
+        let limits = RateLimits {
+            quota_refill_slots: 0,
+            ..LIMITS
+        };
+        let mut bucket = TokenBucket {
+            tokens: 0,
+            last_refill_slot: 1_000,
+        };
+        for _ in 0..10 {
+            bucket.take(&limits, 1_000).unwrap();
+            assert_eq!(bucket.tokens, limits.quota_capacity - 1);
+        }
+    }
+}
@@ -0,0 +1,1137 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    clock::Slot, entrypoint::ProgramResult, hash::hashv, msg, program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    error::GmError,
+    instruction::GmInstruction,
+    rate_limit::{RateLimits, TokenBucket},
+    validation::{normalize_name, NameRules},
+};
+
+/// Seed prefix of greeting account addresses
+pub const GREETING_SEED: &[u8] = b"greeting";
+
+/// Prefix hashed with names into greeting account seeds, keeping these hashes apart from
+/// any other hash of a name
+pub const GREETING_NAME_NAMESPACE: &[u8] = b"gm_program:greeting_name:";
+
+/// Seed prefix of greeter record addresses
+pub const GREETER_RECORD_SEED: &[u8] = b"greeter";
+
+/// Seed prefix of sender record addresses
+pub const SENDER_RECORD_SEED: &[u8] = b"sender";
+
+/// Seed prefix of inbox header addresses
+pub const INBOX_SEED: &[u8] = b"inbox";
+
+/// Seed prefix of inbox page addresses
+pub const INBOX_PAGE_SEED: &[u8] = b"inbox_page";
+
+/// Seed prefix of outbox addresses
+pub const OUTBOX_SEED: &[u8] = b"outbox";
+
+/// Seed prefix of outbox page addresses
+pub const OUTBOX_PAGE_SEED: &[u8] = b"outbox_page";
+
+/// Seed prefix of profile addresses
+pub const PROFILE_SEED: &[u8] = b"profile";
+
+/// Seed prefix of name record addresses
+pub const NAME_RECORD_SEED: &[u8] = b"name";
+
+/// Prefix hashed with names into name record seeds, keeping these hashes apart from any
+/// other hash of a name
+pub const NAME_RECORD_NAMESPACE: &[u8] = b"gm_program:name_record:";
+
+/// Seed prefix of reverse record addresses
+pub const REVERSE_RECORD_SEED: &[u8] = b"reverse";
+
+/// Seed of the config address
+pub const CONFIG_SEED: &[u8] = b"config";
+
+/// Seed of the treasury address
+pub const TREASURY_SEED: &[u8] = b"treasury";
+
+/// Seconds in a calendar day
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Discriminator written over closed accounts, so that an account revived by lamports sent
+/// to it later in the same transaction is not mistaken for any kind of state
+pub const CLOSED_ACCOUNT_DISCRIMINATOR: [u8; 8] = *b"TOMBSTON";
+
+/// Size of the header at the start of every program account: discriminator then version
+pub const HEADER_LEN: usize = 8 + 1;
+
+/// State stored in an account owned by the program
+///
+/// On chain the account starts with `DISCRIMINATOR` and `VERSION`, followed by the Borsh
+/// encoding of the state and zeroed padding up to `LEN`. Discriminators are upper case
+/// ASCII so that no header can be mistaken for the length prefix of a `LegacyGreeting`.
+///
+/// The treasury is the single program account without a header: it holds no data, only
+/// lamports, and is only ever found at `find_treasury_address()`. An empty account fails
+/// to unpack as any state, so it cannot be taken for one either.
+pub trait ProgramAccount: BorshSerialize + BorshDeserialize {
+    /// Identifies the type of state held by the account
+    const DISCRIMINATOR: [u8; 8];
+
+    /// Layout version of the state following the header
+    const VERSION: u8;
+
+    /// Size of the account, header included, with every field at full capacity
+    const LEN: usize;
+
+    /// Decode the state, rejecting accounts of another type or layout version
+    fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < HEADER_LEN || data[..8] != Self::DISCRIMINATOR {
+            msg!("Account does not hold the expected type of state");
+            return Err(GmError::InvalidAccountType.into());
+        }
+        if data[8] != Self::VERSION {
+            msg!("Account layout version {} is not supported", data[8]);
+            return Err(GmError::UnsupportedAccountVersion.into());
+        }
+
+        // Padding after the state is ignored
+        Self::deserialize(&mut &data[HEADER_LEN..]).map_err(|_| GmError::InvalidAccountData.into())
+    }
+
+    /// Encode the header and state into account data and zero everything after them
+    fn pack(&self, dst: &mut [u8]) -> ProgramResult {
+        let mut data = Self::DISCRIMINATOR.to_vec();
+        data.push(Self::VERSION);
+        self.serialize(&mut data)?;
+        pack_padded(&data, dst)
+    }
+}
+
+/// Define the type of state stored in greeting accounts
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct GreetingAccount {
+    /// The only key allowed to change the account
+    pub authority: Pubkey,
+    /// Unix timestamp of the creation of the account
+    pub created_at: i64,
+    /// Unix timestamp of the last GM, zero until the first one
+    pub last_gm_unix_timestamp: i64,
+    /// Slot of the last GM, zero until the first one
+    pub last_gm_slot: u64,
+    /// Number of GMs said to the account
+    pub gm_count: u64,
+    /// Number of different keys that said GM to the account
+    pub distinct_greeters: u64,
+    /// The key that said the first GM to the account
+    pub first_greeter: Option<Pubkey>,
+    pub name: String,
+    /// The last GMs said to the account, if it was created with room for them
+    pub history: GreetingHistory,
+}
+
+impl GreetingAccount {
+    /// Longest name, in bytes, a greeting account can hold
+    pub const MAX_NAME_LEN: usize = 64;
+
+    /// A greeting that has not been said GM to yet
+    pub fn new(authority: Pubkey, name: String, created_at: i64, history_capacity: u8) -> Self {
+        Self {
+            authority,
+            created_at,
+            last_gm_unix_timestamp: 0,
+            last_gm_slot: 0,
+            gm_count: 0,
+            distinct_greeters: 0,
+            first_greeter: None,
+            name,
+            history: GreetingHistory::new(history_capacity),
+        }
+    }
+
+    /// Size of a greeting account with room for `history_capacity` history entries
+    pub fn space(history_capacity: u8) -> usize {
+        Self::LEN + history_capacity as usize * HistoryEntry::LEN
+    }
+
+    /// Size of an account holding this greeting with room for its full history. Names
+    /// migrated from legacy accounts are kept unchecked and can be longer than allowed now.
+    pub fn required_space(&self) -> usize {
+        Self::space(self.history.capacity) + self.name.len().saturating_sub(Self::MAX_NAME_LEN)
+    }
+}
+
+impl ProgramAccount for GreetingAccount {
+    const DISCRIMINATOR: [u8; 8] = *b"GREETING";
+    const VERSION: u8 = 1;
+    // Without history, which is sized when the account is created
+    const LEN: usize = HEADER_LEN
+        + 32
+        + 8
+        + 8
+        + 8
+        + 8
+        + 8
+        + (1 + 32)
+        + 4
+        + Self::MAX_NAME_LEN
+        + GreetingHistory::EMPTY_LEN;
+}
+
+/// A GM kept in the history of a greeting account
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    /// The key that said GM
+    pub greeter: Pubkey,
+    /// SHA-256 of the name said GM to, which the authority may have changed since
+    pub name_hash: [u8; 32],
+    /// Unix timestamp of the GM
+    pub unix_timestamp: i64,
+}
+
+impl HistoryEntry {
+    pub const LEN: usize = 32 + 32 + 8;
+}
+
+/// Ring buffer of the last `capacity` GMs said to a greeting account
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct GreetingHistory {
+    /// Number of entries kept, zero when the history is disabled
+    pub capacity: u8,
+    /// Index of the entry the next GM is written to, the oldest one once the buffer is full
+    pub head: u8,
+    pub entries: Vec<HistoryEntry>,
+}
+
+impl GreetingHistory {
+    /// Largest history a greeting account can be created with
+    pub const MAX_CAPACITY: u8 = 64;
+
+    /// Size of a history with no entries: capacity, head and the u32 length of the entries
+    pub const EMPTY_LEN: usize = 1 + 1 + 4;
+
+    pub fn new(capacity: u8) -> Self {
+        Self {
+            capacity,
+            head: 0,
+            entries: Vec::with_capacity(capacity as usize),
+        }
+    }
+
+    /// Record a GM, overwriting the oldest one once the buffer is full
+    pub fn push(&mut self, entry: HistoryEntry) {
+        if self.capacity > 0 {
+            ring_push(
+                &mut self.entries,
+                &mut self.head,
+                self.capacity as usize,
+                entry,
+            );
+        }
+    }
+
+    /// Iterate over the recorded GMs, most recent first
+    pub fn iter_recent(&self) -> impl Iterator<Item = &HistoryEntry> {
+        ring_iter_recent(&self.entries, self.head)
+    }
+}
+
+/// What a greeter has said to one greeting account, created on its first GM to it
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct GreeterRecord {
+    /// The greeting account said GM to
+    pub greeting: Pubkey,
+    /// The key that said GM
+    pub greeter: Pubkey,
+    /// Number of GMs the greeter said to the greeting account
+    pub gm_count: u64,
+    /// Slot of the last GM of the greeter to the greeting account
+    pub last_gm_slot: Slot,
+}
+
+impl ProgramAccount for GreeterRecord {
+    const DISCRIMINATOR: [u8; 8] = *b"GREETERR";
+    const VERSION: u8 = 1;
+    const LEN: usize = HEADER_LEN + 32 + 32 + 8 + 8;
+}
+
+/// What a sender has said to one recipient, created on its first GM to the recipient
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct SenderRecord {
+    /// The wallet said GM to
+    pub recipient: Pubkey,
+    /// The key that said GM
+    pub sender: Pubkey,
+    /// Number of GMs the sender said to the recipient
+    pub gm_count: u64,
+    /// Slot of the last GM of the sender to the recipient
+    pub last_gm_slot: Slot,
+}
+
+impl ProgramAccount for SenderRecord {
+    const DISCRIMINATOR: [u8; 8] = *b"SENDERRC";
+    const VERSION: u8 = 1;
+    const LEN: usize = HEADER_LEN + 32 + 32 + 8 + 8;
+}
+
+/// GMs said to a recipient, created with its first page on the first GM to it
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct InboxHeader {
+    /// The key said GM to
+    pub recipient: Pubkey,
+    /// Number of GMs said to the recipient, which locates the page the next one goes to
+    pub gm_count: u64,
+    /// Number of pages created so far
+    pub page_count: u32,
+}
+
+impl InboxHeader {
+    /// Index of the page the next GM is appended to
+    pub fn current_page(&self) -> u32 {
+        (self.gm_count / InboxPage::CAPACITY as u64) as u32
+    }
+}
+
+impl ProgramAccount for InboxHeader {
+    const DISCRIMINATOR: [u8; 8] = *b"INBOXHDR";
+    const VERSION: u8 = 1;
+    const LEN: usize = HEADER_LEN + 32 + 8 + 4;
+}
+
+/// A GM received in an inbox
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct InboxEntry {
+    /// The key that said GM
+    pub sender: Pubkey,
+    /// Unix timestamp of the GM
+    pub unix_timestamp: i64,
+    /// Slot of the GM
+    pub slot: u64,
+}
+
+impl InboxEntry {
+    pub const LEN: usize = 32 + 8 + 8;
+}
+
+/// A page of up to `CAPACITY` GMs of an inbox, in the order they were said
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct InboxPage {
+    /// The key said GM to
+    pub recipient: Pubkey,
+    /// Position of the page in the inbox, starting at zero
+    pub index: u32,
+    pub entries: Vec<InboxEntry>,
+}
+
+impl InboxPage {
+    /// Number of GMs a page holds before the next one is started
+    pub const CAPACITY: usize = 32;
+}
+
+impl ProgramAccount for InboxPage {
+    const DISCRIMINATOR: [u8; 8] = *b"INBOXPAG";
+    const VERSION: u8 = 1;
+    const LEN: usize = HEADER_LEN + 32 + 4 + 4 + Self::CAPACITY * InboxEntry::LEN;
+}
+
+/// A GM sent from an outbox
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct OutboxEntry {
+    /// The greeting account or key said GM to
+    pub recipient: Pubkey,
+    /// Unix timestamp of the GM
+    pub unix_timestamp: i64,
+}
+
+impl OutboxEntry {
+    pub const LEN: usize = 32 + 8;
+}
+
+/// GMs said by a sender, created with its first page on its first GM.
+/// Also holds the quota of GMs the sender can still say.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct Outbox {
+    /// The key that said GM
+    pub sender: Pubkey,
+    /// Number of GMs said by the sender, which locates the page the next one goes to
+    pub gm_count: u64,
+    /// GMs the sender can say before being rate limited
+    pub quota: TokenBucket,
+    /// Number of pages created so far
+    pub page_count: u32,
+}
+
+impl Outbox {
+    pub fn new(sender: Pubkey, quota: TokenBucket) -> Self {
+        Self {
+            sender,
+            gm_count: 0,
+            quota,
+            page_count: 0,
+        }
+    }
+
+    /// Index of the page the next GM is appended to
+    pub fn current_page(&self) -> u32 {
+        (self.gm_count / OutboxPage::CAPACITY as u64) as u32
+    }
+}
+
+impl ProgramAccount for Outbox {
+    const DISCRIMINATOR: [u8; 8] = *b"OUTBOXGM";
+    const VERSION: u8 = 1;
+    const LEN: usize = HEADER_LEN + 32 + 8 + TokenBucket::LEN + 4;
+}
+
+/// A page of up to `CAPACITY` GMs of an outbox, in the order they were said
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct OutboxPage {
+    /// The key that said GM
+    pub sender: Pubkey,
+    /// Position of the page in the outbox, starting at zero
+    pub index: u32,
+    pub entries: Vec<OutboxEntry>,
+}
+
+impl OutboxPage {
+    /// Number of GMs a page holds before the next one is started
+    pub const CAPACITY: usize = 32;
+}
+
+impl ProgramAccount for OutboxPage {
+    const DISCRIMINATOR: [u8; 8] = *b"OUTBOXPG";
+    const VERSION: u8 = 1;
+    const LEN: usize = HEADER_LEN + 32 + 4 + 4 + Self::CAPACITY * OutboxEntry::LEN;
+}
+
+/// Consecutive calendar days a wallet said GM on
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct GmStreak {
+    /// Days in the streak ending on `last_day`, zero before the first GM
+    pub current: u32,
+    /// Longest streak ever reached
+    pub longest: u32,
+    /// Last day a GM was said on, in days since the Unix epoch in the wallet's time zone
+    pub last_day: i64,
+}
+
+impl GmStreak {
+    pub const LEN: usize = 4 + 4 + 8;
+
+    /// Count a GM said on `day`, starting a new streak if the previous day was missed.
+    /// A day before the last one, as a time zone moved west gives, was already counted.
+    pub fn record(&mut self, day: i64) -> Result<(), GmError> {
+        if self.current > 0 && day <= self.last_day {
+            return Ok(());
+        }
+
+        self.current = if self.current > 0 && day == self.last_day + 1 {
+            self.current.checked_add(1).ok_or(GmError::Overflow)?
+        } else {
+            1
+        };
+        self.longest = self.longest.max(self.current);
+        self.last_day = day;
+
+        Ok(())
+    }
+}
+
+/// Image shown for a wallet, pinned to its content so a changed image is noticed
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct Avatar {
+    /// Where the image is served from
+    pub uri: String,
+    /// SHA-256 of the image
+    pub content_hash: [u8; 32],
+}
+
+impl Avatar {
+    /// Longest URI, in bytes, a profile can hold
+    pub const MAX_URI_LEN: usize = 200;
+
+    /// URI schemes an avatar can be served from
+    pub const URI_SCHEMES: [&'static str; 3] = ["https://", "ipfs://", "ar://"];
+
+    pub const MAX_LEN: usize = 4 + Self::MAX_URI_LEN + 32;
+}
+
+/// Per wallet state, created on its first GM or with `CreateProfile`
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct Profile {
+    /// The wallet the profile belongs to
+    pub wallet: Pubkey,
+    /// Offset of the wallet's time zone from UTC, deciding when its days start
+    pub utc_offset_minutes: i16,
+    pub streak: GmStreak,
+    /// Name shown for the wallet, empty until set
+    pub display_name: String,
+    pub avatar: Option<Avatar>,
+    /// Short text about the wallet, may be empty
+    pub bio: String,
+}
+
+impl Profile {
+    /// Offsets of the time zones in use, from UTC-12:00 to UTC+14:00
+    pub const MIN_UTC_OFFSET_MINUTES: i16 = -12 * 60;
+    pub const MAX_UTC_OFFSET_MINUTES: i16 = 14 * 60;
+
+    /// Longest display name, in bytes, a profile can hold
+    pub const MAX_DISPLAY_NAME_LEN: usize = 32;
+
+    /// Longest bio, in bytes, a profile can hold
+    pub const MAX_BIO_LEN: usize = 160;
+
+    pub fn new(wallet: Pubkey) -> Self {
+        Self {
+            wallet,
+            utc_offset_minutes: 0,
+            streak: GmStreak::default(),
+            display_name: String::new(),
+            avatar: None,
+            bio: String::new(),
+        }
+    }
+
+    /// Day of `unix_timestamp` in the wallet's time zone, in days since the Unix epoch
+    pub fn local_day(&self, unix_timestamp: i64) -> i64 {
+        day_at(unix_timestamp, self.utc_offset_minutes)
+    }
+
+    /// Move the wallet to another time zone at `unix_timestamp`. Moving to the next day
+    /// once the GM of the current day is counted would count another GM minutes later,
+    /// so it waits for the next day to start.
+    pub fn set_utc_offset(
+        &mut self,
+        utc_offset_minutes: i16,
+        unix_timestamp: i64,
+    ) -> Result<(), GmError> {
+        let day = self.local_day(unix_timestamp);
+        let new_day = day_at(unix_timestamp, utc_offset_minutes);
+        if self.streak.current > 0 && day <= self.streak.last_day && new_day > day {
+            msg!("UTC offset can move to the next day once today ends");
+            return Err(GmError::UtcOffsetSkipsDay);
+        }
+        self.utc_offset_minutes = utc_offset_minutes;
+
+        Ok(())
+    }
+}
+
+/// Day of `unix_timestamp` in the time zone `utc_offset_minutes` from UTC, in days since
+/// the Unix epoch
+fn day_at(unix_timestamp: i64, utc_offset_minutes: i16) -> i64 {
+    unix_timestamp
+        .saturating_add(utc_offset_minutes as i64 * 60)
+        .div_euclid(SECONDS_PER_DAY)
+}
+
+impl ProgramAccount for Profile {
+    const DISCRIMINATOR: [u8; 8] = *b"PROFILEE";
+    const VERSION: u8 = 1;
+    const LEN: usize = HEADER_LEN
+        + 32
+        + 2
+        + GmStreak::LEN
+        + 4
+        + Self::MAX_DISPLAY_NAME_LEN
+        + (1 + Avatar::MAX_LEN)
+        + 4
+        + Self::MAX_BIO_LEN;
+}
+
+/// Registration of a name to the wallet owning it, at the address derived from the name
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct NameRecord {
+    /// The wallet allowed to transfer or release the name, and greeted by it
+    pub owner: Pubkey,
+    /// Unix timestamp of the registration of the name by its first owner
+    pub registered_at: i64,
+    /// The name, normalized with `normalize_name`
+    pub name: String,
+}
+
+impl ProgramAccount for NameRecord {
+    const DISCRIMINATOR: [u8; 8] = *b"NAMERECD";
+    const VERSION: u8 = 1;
+    /// Room for names up to the longest `max_name_len` allowed by any settings
+    const LEN: usize = HEADER_LEN + 32 + 8 + 4 + GreetingAccount::MAX_NAME_LEN;
+}
+
+/// Primary name of a wallet, at the address derived from the wallet
+///
+/// Only points to a name registered to the wallet: it is set when the wallet registers its
+/// first name and closed when that name is transferred or released.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ReverseRecord {
+    pub wallet: Pubkey,
+    /// The primary name, normalized with `normalize_name`
+    pub name: String,
+}
+
+impl ProgramAccount for ReverseRecord {
+    const DISCRIMINATOR: [u8; 8] = *b"REVERSEN";
+    const VERSION: u8 = 1;
+    const LEN: usize = HEADER_LEN + 32 + 4 + GreetingAccount::MAX_NAME_LEN;
+}
+
+/// Lamports charged for GMs, on top of transaction fees
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct FeeSchedule {
+    pub say_gm_lamports: u64,
+    pub say_gm_to_lamports: u64,
+}
+
+impl FeeSchedule {
+    pub const LEN: usize = 8 + 8;
+}
+
+/// Settings of the program the admin can change without redeploying it
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ConfigSettings {
+    /// Longest greeting name allowed, in bytes, at most `GreetingAccount::MAX_NAME_LEN`
+    pub max_name_len: u16,
+    pub rate_limits: RateLimits,
+    pub fees: FeeSchedule,
+    /// Slots between the admin proposing a change of settings, fee exempt list or admin and
+    /// the change taking effect, zero to apply changes other than of the admin immediately
+    pub timelock_slots: u64,
+}
+
+impl ConfigSettings {
+    pub const LEN: usize = 2 + RateLimits::LEN + FeeSchedule::LEN + 8;
+
+    /// Longest timelock, about 30 days: as lowering it is timelocked too, a longer one
+    /// could keep the config from ever changing again
+    pub const MAX_TIMELOCK_SLOTS: u64 = 30 * 24 * 60 * 60 * 5 / 2;
+
+    /// Rules greeting names must follow
+    pub fn name_rules(&self) -> NameRules {
+        NameRules {
+            max_len: self.max_name_len as usize,
+            ..NameRules::default()
+        }
+    }
+
+    /// Check that the settings can be applied
+    pub fn validate(&self) -> Result<(), GmError> {
+        if self.max_name_len == 0 || self.max_name_len as usize > GreetingAccount::MAX_NAME_LEN {
+            msg!(
+                "Max name length must be between 1 and {}",
+                GreetingAccount::MAX_NAME_LEN
+            );
+            return Err(GmError::InvalidConfig);
+        }
+        if self.rate_limits.quota_capacity == 0 {
+            msg!("GM quota capacity must not be zero");
+            return Err(GmError::InvalidConfig);
+        }
+        if self.timelock_slots > Self::MAX_TIMELOCK_SLOTS {
+            msg!(
+                "Timelock must be at most {} slots",
+                Self::MAX_TIMELOCK_SLOTS
+            );
+            return Err(GmError::InvalidConfig);
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for ConfigSettings {
+    /// The settings in effect until the config is initialized
+    fn default() -> Self {
+        Self {
+            max_name_len: GreetingAccount::MAX_NAME_LEN as u16,
+            rate_limits: RateLimits::default(),
+            fees: FeeSchedule::default(),
+            timelock_slots: 0,
+        }
+    }
+}
+
+/// Settings proposed by the admin, waiting for the timelock
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PendingSettings {
+    pub settings: ConfigSettings,
+    /// First slot the settings are in effect
+    pub effective_slot: Slot,
+}
+
+/// Admin proposed by the current admin, which must accept the role
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PendingAdmin {
+    pub admin: Pubkey,
+    /// First slot the proposed admin can accept the role
+    pub effective_slot: Slot,
+}
+
+/// Fee exempt list proposed by the admin, waiting for the timelock
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct PendingFeeExempt {
+    pub fee_exempt: Vec<Pubkey>,
+    /// First slot the list is in effect
+    pub effective_slot: Slot,
+}
+
+/// Global state of the program, at `find_config_address()`
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct Config {
+    /// The only key allowed to change the settings
+    pub admin: Pubkey,
+    pub settings: ConfigSettings,
+    /// Instructions halted by the admin, as the bits given by `GmInstruction::pause_bit`.
+    /// Pausing is never timelocked.
+    pub paused_instructions: u64,
+    pub pending_settings: Option<PendingSettings>,
+    pub pending_admin: Option<PendingAdmin>,
+    /// Wallets that never pay fees, at most `MAX_FEE_EXEMPT` of them
+    pub fee_exempt: Vec<Pubkey>,
+    pub pending_fee_exempt: Option<PendingFeeExempt>,
+}
+
+impl Config {
+    /// Most wallets the fee exempt list can hold
+    pub const MAX_FEE_EXEMPT: usize = 16;
+
+    /// The config before it is initialized, with default settings and no admin
+    pub fn uninitialized() -> Self {
+        Self {
+            admin: Pubkey::default(),
+            settings: ConfigSettings::default(),
+            paused_instructions: 0,
+            pending_settings: None,
+            pending_admin: None,
+            fee_exempt: Vec::new(),
+            pending_fee_exempt: None,
+        }
+    }
+
+    pub fn is_fee_exempt(&self, wallet: &Pubkey) -> bool {
+        self.fee_exempt.contains(wallet)
+    }
+
+    /// Put the pending settings and fee exempt list in effect once their timelock has
+    /// elapsed at `slot`
+    pub fn apply_pending_changes(&mut self, slot: Slot) {
+        if let Some(pending) = self.pending_settings {
+            if slot >= pending.effective_slot {
+                self.settings = pending.settings;
+                self.pending_settings = None;
+            }
+        }
+        if let Some(pending) = self.pending_fee_exempt.take() {
+            if slot >= pending.effective_slot {
+                self.fee_exempt = pending.fee_exempt;
+            } else {
+                self.pending_fee_exempt = Some(pending);
+            }
+        }
+    }
+
+    /// Whether the admin halted the instruction
+    pub fn is_paused(&self, instruction: &GmInstruction) -> bool {
+        instruction
+            .pause_bit()
+            .is_some_and(|bit| self.paused_instructions & bit != 0)
+    }
+}
+
+impl ProgramAccount for Config {
+    const DISCRIMINATOR: [u8; 8] = *b"GMCONFIG";
+    const VERSION: u8 = 1;
+    const LEN: usize = HEADER_LEN
+        + 32
+        + ConfigSettings::LEN
+        + 8
+        + (1 + ConfigSettings::LEN + 8)
+        + (1 + 32 + 8)
+        + (4 + Self::MAX_FEE_EXEMPT * 32)
+        + (1 + 4 + Self::MAX_FEE_EXEMPT * 32 + 8);
+}
+
+/// Layout of greeting accounts created before the account header: a bare Borsh name
+/// followed by zeroed padding. It is also the payload of legacy instructions.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct LegacyGreeting {
+    pub name: String,
+}
+
+impl LegacyGreeting {
+    /// Decode a legacy greeting, ignoring the padding after the name
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        Self::deserialize(&mut &data[..]).map_err(|_| GmError::InvalidAccountData.into())
+    }
+
+    /// Encode the greeting into account data and zero everything after it
+    pub fn pack(&self, dst: &mut [u8]) -> ProgramResult {
+        pack_padded(&self.try_to_vec()?, dst)
+    }
+}
+
+/// Write an entry at the head of a ring buffer of `capacity` entries and advance the head
+fn ring_push<T>(entries: &mut Vec<T>, head: &mut u8, capacity: usize, entry: T) {
+    let index = *head as usize;
+    if index < entries.len() {
+        entries[index] = entry;
+    } else {
+        entries.push(entry);
+    }
+    *head = ((index + 1) % capacity) as u8;
+}
+
+/// Iterate over the entries of a ring buffer, most recent first
+fn ring_iter_recent<T>(entries: &[T], head: u8) -> impl Iterator<Item = &T> {
+    // Once the buffer is full the oldest entries start at the head
+    let (newer, older) = entries.split_at(head as usize);
+    older.iter().chain(newer.iter()).rev()
+}
+
+/// Copy encoded state into account data, zeroing the rest of the account
+fn pack_padded(data: &[u8], dst: &mut [u8]) -> ProgramResult {
+    if data.len() > dst.len() {
+        msg!("Account holds {} bytes, {} needed", dst.len(), data.len());
+        return Err(GmError::AccountTooSmall.into());
+    }
+
+    let (head, padding) = dst.split_at_mut(data.len());
+    head.copy_from_slice(data);
+    padding.fill(0);
+
+    Ok(())
+}
+
+/// Hash of `name` used as the seed of greeting account addresses: the SHA-256 of
+/// `GREETING_NAME_NAMESPACE` followed by the name normalized with `normalize_name`.
+/// Names of any length fit in a seed this way, and names differing only in case share
+/// an address.
+pub fn greeting_name_seed(name: &str) -> [u8; 32] {
+    hashv(&[GREETING_NAME_NAMESPACE, normalize_name(name).as_bytes()]).to_bytes()
+}
+
+/// Derive the address of the greeting account created by `payer` for `name`
+pub fn find_greeting_address(program_id: &Pubkey, payer: &Pubkey, name: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[GREETING_SEED, payer.as_ref(), &greeting_name_seed(name)],
+        program_id,
+    )
+}
+
+/// Derive the address of the record of what `greeter` has said to the `greeting` account
+pub fn find_greeter_record_address(
+    program_id: &Pubkey,
+    greeting: &Pubkey,
+    greeter: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[GREETER_RECORD_SEED, greeting.as_ref(), greeter.as_ref()],
+        program_id,
+    )
+}
+
+/// Derive the address of the record of what `sender` has said to `recipient`
+pub fn find_sender_record_address(
+    program_id: &Pubkey,
+    recipient: &Pubkey,
+    sender: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[SENDER_RECORD_SEED, recipient.as_ref(), sender.as_ref()],
+        program_id,
+    )
+}
+
+/// Derive the address of the inbox header of `recipient`
+pub fn find_inbox_address(program_id: &Pubkey, recipient: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[INBOX_SEED, recipient.as_ref()], program_id)
+}
+
+/// Derive the address of page `index` of the inbox of `recipient`
+pub fn find_inbox_page_address(
+    program_id: &Pubkey,
+    recipient: &Pubkey,
+    index: u32,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[INBOX_PAGE_SEED, recipient.as_ref(), &index.to_le_bytes()],
+        program_id,
+    )
+}
+
+/// Derive the address of the outbox of `sender`
+pub fn find_outbox_address(program_id: &Pubkey, sender: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[OUTBOX_SEED, sender.as_ref()], program_id)
+}
+
+/// Derive the address of page `index` of the outbox of `sender`
+pub fn find_outbox_page_address(program_id: &Pubkey, sender: &Pubkey, index: u32) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[OUTBOX_PAGE_SEED, sender.as_ref(), &index.to_le_bytes()],
+        program_id,
+    )
+}
+
+/// Derive the address of the profile of `wallet`
+pub fn find_profile_address(program_id: &Pubkey, wallet: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PROFILE_SEED, wallet.as_ref()], program_id)
+}
+
+/// Hash of `name` used as the seed of name record addresses: the SHA-256 of
+/// `NAME_RECORD_NAMESPACE` followed by the name normalized with `normalize_name`
+pub fn name_record_seed(name: &str) -> [u8; 32] {
+    hashv(&[NAME_RECORD_NAMESPACE, normalize_name(name).as_bytes()]).to_bytes()
+}
+
+/// Derive the address of the record of `name`
+pub fn find_name_record_address(program_id: &Pubkey, name: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[NAME_RECORD_SEED, &name_record_seed(name)], program_id)
+}
+
+/// Derive the address of the reverse record of `wallet`
+pub fn find_reverse_record_address(program_id: &Pubkey, wallet: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[REVERSE_RECORD_SEED, wallet.as_ref()], program_id)
+}
+
+/// Derive the address of the config of the program
+pub fn find_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CONFIG_SEED], program_id)
+}
+
+/// Derive the address of the treasury collecting the fees of the program. The treasury
+/// holds no data, and so no header, see `ProgramAccount`.
+pub fn find_treasury_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[TREASURY_SEED], program_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streak_starts_on_first_gm() {
+        let mut streak = GmStreak::default();
+        streak.record(19_000).unwrap();
+        assert_eq!(
+            streak,
+            GmStreak {
+                current: 1,
+                longest: 1,
+                last_day: 19_000,
+            }
+        );
+    }
+
+    #[test]
+    fn streak_counts_a_day_once() {
+        let mut streak = GmStreak::default();
+        streak.record(19_000).unwrap();
+        streak.record(19_000).unwrap();
+        assert_eq!(streak.current, 1);
+        assert_eq!(streak.longest, 1);
+    }
+
+    #[test]
+    fn streak_grows_on_the_next_day() {
+        let mut streak = GmStreak::default();
+        streak.record(19_000).unwrap();
+        streak.record(19_001).unwrap();
+        streak.record(19_002).unwrap();
+        assert_eq!(streak.current, 3);
+        assert_eq!(streak.longest, 3);
+        assert_eq!(streak.last_day, 19_002);
+    }
+
+    #[test]
+    fn streak_restarts_after_a_missed_day() {
+        let mut streak = GmStreak::default();
+        streak.record(19_000).unwrap();
+        streak.record(19_001).unwrap();
+        streak.record(19_003).unwrap();
+        assert_eq!(streak.current, 1);
+        assert_eq!(streak.longest, 2);
+        assert_eq!(streak.last_day, 19_003);
+    }
+
+    #[test]
+    fn streak_starts_on_day_zero() {
+        // The epoch day must not be mistaken for the empty streak
+        let mut streak = GmStreak::default();
+        streak.record(0).unwrap();
+        streak.record(1).unwrap();
+        assert_eq!(streak.current, 2);
+    }
+
+    #[test]
+    fn local_day_follows_utc_offset() {
+        // 2022-01-01T23:30:00Z
+        let unix_timestamp = 18_993 * SECONDS_PER_DAY + 23 * 60 * 60 + 30 * 60;
+        let profile = |utc_offset_minutes| Profile {
+            utc_offset_minutes,
+            ..Profile::new(Pubkey::new_unique())
+        };
+
+        assert_eq!(profile(0).local_day(unix_timestamp), 18_993);
+        assert_eq!(profile(29).local_day(unix_timestamp), 18_993);
+        assert_eq!(profile(30).local_day(unix_timestamp), 18_994);
+        assert_eq!(
+            profile(Profile::MAX_UTC_OFFSET_MINUTES).local_day(unix_timestamp),
+            18_994
+        );
+        assert_eq!(
+            profile(Profile::MIN_UTC_OFFSET_MINUTES).local_day(unix_timestamp),
+            18_993
+        );
+    }
+
+    #[test]
+    fn local_day_before_midnight_utc_west_of_greenwich() {
+        // 2022-01-02T03:00:00Z is still January 1st at UTC-05:00
+        let unix_timestamp = 18_994 * SECONDS_PER_DAY + 3 * 60 * 60;
+        let profile = Profile {
+            utc_offset_minutes: -5 * 60,
+            ..Profile::new(Pubkey::new_unique())
+        };
+        assert_eq!(profile.local_day(unix_timestamp), 18_993);
+    }
+
+    #[test]
+    fn local_day_before_the_epoch() {
+        let profile = Profile {
+            utc_offset_minutes: -60,
+            ..Profile::new(Pubkey::new_unique())
+        };
+        assert_eq!(profile.local_day(0), -1);
+    }
+
+    #[test]
+    fn outbox_moves_to_the_next_page_once_one_is_full() {
+        let quota = TokenBucket {
+            tokens: 0,
+            last_refill_slot: 0,
+        };
+        let outbox = |gm_count| Outbox {
+            gm_count,
+            ..Outbox::new(Pubkey::new_unique(), quota)
+        };
+        assert_eq!(outbox(0).current_page(), 0);
+        assert_eq!(outbox(OutboxPage::CAPACITY as u64 - 1).current_page(), 0);
+        assert_eq!(outbox(OutboxPage::CAPACITY as u64).current_page(), 1);
+        assert_eq!(
+            outbox(10 * OutboxPage::CAPACITY as u64 + 1).current_page(),
+            10
+        );
+    }
+
+    #[test]
+    fn streak_keeps_a_day_before_the_last_one() {
+        let mut streak = GmStreak::default();
+        streak.record(19_000).unwrap();
+        streak.record(19_001).unwrap();
+        // A time zone moved west puts the wallet back on the previous day
+        streak.record(19_000).unwrap();
+        assert_eq!(
+            streak,
+            GmStreak {
+                current: 2,
+                longest: 2,
+                last_day: 19_001,
+            }
+        );
+        streak.record(19_002).unwrap();
+        assert_eq!(streak.current, 3);
+    }
+
+    /// A profile in UTC that said GM at `unix_timestamp`
+    fn profile_after_gm(unix_timestamp: i64) -> Profile {
+        let mut profile = Profile::new(Pubkey::new_unique());
+        profile
+            .streak
+            .record(profile.local_day(unix_timestamp))
+            .unwrap();
+        profile
+    }
+
+    #[test]
+    fn utc_offset_cannot_skip_to_the_next_day_after_a_gm() {
+        // 2022-01-01T23:30:00Z
+        let unix_timestamp = 18_993 * SECONDS_PER_DAY + 23 * 60 * 60 + 30 * 60;
+        let mut profile = profile_after_gm(unix_timestamp);
+
+        // UTC+01:00 is already on the next day
+        assert_eq!(
+            profile.set_utc_offset(60, unix_timestamp + 60),
+            Err(GmError::UtcOffsetSkipsDay)
+        );
+        assert_eq!(profile.utc_offset_minutes, 0);
+        // UTC+00:15 is still on the same day
+        profile.set_utc_offset(15, unix_timestamp + 60).unwrap();
+
+        // Once the day ends in the current time zone, the wallet can move east again
+        let next_day = 18_994 * SECONDS_PER_DAY;
+        profile.set_utc_offset(14 * 60, next_day).unwrap();
+    }
+
+    #[test]
+    fn utc_offset_can_move_west_after_a_gm() {
+        // 2022-01-02T00:30:00Z
+        let unix_timestamp = 18_994 * SECONDS_PER_DAY + 30 * 60;
+        let mut profile = profile_after_gm(unix_timestamp);
+
+        profile
+            .set_utc_offset(-8 * 60, unix_timestamp + 60)
+            .unwrap();
+        let day = profile.local_day(unix_timestamp + 60);
+        assert_eq!(day, 18_993);
+        // The earlier day neither counts again nor breaks the streak
+        profile.streak.record(day).unwrap();
+        assert_eq!(
+            profile.streak,
+            GmStreak {
+                current: 1,
+                longest: 1,
+                last_day: 18_994,
+            }
+        );
+    }
+
+    #[test]
+    fn utc_offset_can_move_to_a_day_without_a_gm() {
+        // A day after the last GM, the next day can start in any time zone
+        let unix_timestamp = 18_994 * SECONDS_PER_DAY + 23 * 60 * 60;
+        let mut profile = profile_after_gm(unix_timestamp - SECONDS_PER_DAY);
+        profile.set_utc_offset(14 * 60, unix_timestamp).unwrap();
+
+        // Nor is it restricted before the first GM
+        let mut profile = Profile::new(Pubkey::new_unique());
+        profile.set_utc_offset(14 * 60, unix_timestamp).unwrap();
+    }
+
+    #[test]
+    fn name_record_address_fits_names_longer_than_a_seed() {
+        let program_id = Pubkey::new_unique();
+        let name = "g".repeat(GreetingAccount::MAX_NAME_LEN);
+        let (address, _) = find_name_record_address(&program_id, &name);
+        assert_eq!(
+            find_name_record_address(&program_id, &name.to_uppercase()).0,
+            address
+        );
+        assert_ne!(find_name_record_address(&program_id, "g").0, address);
+    }
+
+    #[test]
+    fn name_record_seed_is_apart_from_greeting_name_seed() {
+        assert_ne!(name_record_seed("gm"), greeting_name_seed("gm"));
+    }
+
+    #[test]
+    fn name_record_holds_the_longest_name() {
+        let record = NameRecord {
+            owner: Pubkey::new_unique(),
+            registered_at: 0,
+            name: "g".repeat(GreetingAccount::MAX_NAME_LEN),
+        };
+        let mut data = vec![0; NameRecord::LEN];
+        record.pack(&mut data).unwrap();
+        assert_eq!(NameRecord::unpack(&data).unwrap(), record);
+    }
+}
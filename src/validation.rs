@@ -0,0 +1,260 @@
+use solana_program::msg;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{
+    error::GmError,
+    state::{Avatar, GreetingAccount},
+};
+
+/// Rules a name must follow before it is stored or logged by the program
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NameRules {
+    /// Longest name allowed, in bytes
+    pub max_len: usize,
+    /// Allow U+200D ZERO WIDTH JOINER, which joins emoji into a single glyph
+    pub allow_zero_width_joiner: bool,
+}
+
+impl Default for NameRules {
+    fn default() -> Self {
+        Self {
+            max_len: GreetingAccount::MAX_NAME_LEN,
+            allow_zero_width_joiner: false,
+        }
+    }
+}
+
+impl NameRules {
+    /// Check a name against the rules, failing with the error of the first violation.
+    /// Names are not normalized here since they are stored as sent: clients must send
+    /// them in NFC.
+    pub fn validate(&self, name: &str) -> Result<(), GmError> {
+        if name.is_empty() {
+            msg!("Name is empty");
+            return Err(GmError::NameEmpty);
+        }
+        if name.len() > self.max_len {
+            msg!(
+                "Name is {} bytes, at most {} allowed",
+                name.len(),
+                self.max_len
+            );
+            return Err(GmError::NameTooLong);
+        }
+
+        if !unicode_normalization::is_nfc(name) {
+            msg!("Name is not NFC normalized");
+            return Err(GmError::NameNotNormalized);
+        }
+
+        for c in name.chars() {
+            if c.is_control() || is_line_separator(c) {
+                msg!("Name contains control character U+{:04X}", c as u32);
+                return Err(GmError::NameHasControlCharacter);
+            }
+            if is_bidi_control(c) {
+                msg!("Name contains bidirectional control U+{:04X}", c as u32);
+                return Err(GmError::NameHasBidiOverride);
+            }
+            if is_zero_width(c) && !(self.allow_zero_width_joiner && c == '\u{200D}') {
+                msg!("Name contains zero width character U+{:04X}", c as u32);
+                return Err(GmError::NameHasZeroWidthCharacter);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Canonical form of a name in the registry, so that names differing only in case or in
+/// the composition of their characters are the same name
+pub fn normalize_name(name: &str) -> String {
+    name.to_lowercase().nfc().collect()
+}
+
+/// Check that an avatar URI fits in a profile, uses a supported scheme and is made of
+/// visible ASCII characters only, so it cannot point somewhere else than it reads
+pub fn validate_avatar_uri(uri: &str) -> Result<(), GmError> {
+    if uri.len() > Avatar::MAX_URI_LEN {
+        msg!(
+            "Avatar URI is {} bytes, at most {} allowed",
+            uri.len(),
+            Avatar::MAX_URI_LEN
+        );
+        return Err(GmError::InvalidAvatarUri);
+    }
+
+    if !Avatar::URI_SCHEMES
+        .iter()
+        .any(|scheme| uri.len() > scheme.len() && uri.starts_with(scheme))
+    {
+        msg!(
+            "Avatar URI must start with one of {:?}",
+            Avatar::URI_SCHEMES
+        );
+        return Err(GmError::InvalidAvatarUri);
+    }
+
+    if let Some(c) = uri.chars().find(|c| !c.is_ascii_graphic()) {
+        msg!("Avatar URI contains U+{:04X}", c as u32);
+        return Err(GmError::InvalidAvatarUri);
+    }
+
+    Ok(())
+}
+
+/// Characters that change the direction of the text around them, which can make a name
+/// render as something else entirely
+fn is_bidi_control(c: char) -> bool {
+    matches!(
+        c,
+        '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}'
+    )
+}
+
+/// Line breaks outside of the control characters, which log viewers render like newlines
+fn is_line_separator(c: char) -> bool {
+    matches!(c, '\u{2028}' | '\u{2029}')
+}
+
+/// Invisible characters that let two names look the same
+fn is_zero_width(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00AD}'
+            | '\u{180E}'
+            | '\u{200B}'..='\u{200D}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{FEFF}'
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_following_the_rules_is_valid() {
+        assert_eq!(NameRules::default().validate("GM world ☀️"), Ok(()));
+    }
+
+    #[test]
+    fn empty_name_is_rejected() {
+        assert_eq!(NameRules::default().validate(""), Err(GmError::NameEmpty));
+    }
+
+    #[test]
+    fn name_longer_than_max_len_is_rejected() {
+        let rules = NameRules {
+            max_len: 4,
+            ..NameRules::default()
+        };
+        assert_eq!(rules.validate("gm!!"), Ok(()));
+        assert_eq!(rules.validate("gm!!!"), Err(GmError::NameTooLong));
+        // The limit is in bytes, not characters
+        assert_eq!(rules.validate("gmé!"), Err(GmError::NameTooLong));
+    }
+
+    #[test]
+    fn name_not_in_nfc_is_rejected() {
+        assert_eq!(
+            NameRules::default().validate("gme\u{0301}"),
+            Err(GmError::NameNotNormalized)
+        );
+    }
+
+    #[test]
+    fn name_with_line_breaks_is_rejected() {
+        for name in [
+            "GM\nworld",
+            "GM\rworld",
+            "GM\u{0085}world",
+            "GM\u{2028}world",
+            "GM\u{2029}world",
+        ] {
+            assert_eq!(
+                NameRules::default().validate(name),
+                Err(GmError::NameHasControlCharacter),
+                "{:?}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn name_with_bidi_controls_is_rejected() {
+        for name in ["GM\u{202E}dlrow", "GM\u{2067}world", "GM\u{200F}world"] {
+            assert_eq!(
+                NameRules::default().validate(name),
+                Err(GmError::NameHasBidiOverride),
+                "{:?}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn name_with_invisible_characters_is_rejected() {
+        for name in [
+            "GM\u{200B}world",
+            "GM\u{00AD}world",
+            "GM\u{2061}world",
+            "GM\u{2064}world",
+            "GM\u{FEFF}world",
+        ] {
+            assert_eq!(
+                NameRules::default().validate(name),
+                Err(GmError::NameHasZeroWidthCharacter),
+                "{:?}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn zero_width_joiner_is_only_allowed_when_enabled() {
+        let family = "\u{1F468}\u{200D}\u{1F469}";
+        assert_eq!(
+            NameRules::default().validate(family),
+            Err(GmError::NameHasZeroWidthCharacter)
+        );
+
+        let rules = NameRules {
+            allow_zero_width_joiner: true,
+            ..NameRules::default()
+        };
+        assert_eq!(rules.validate(family), Ok(()));
+        // Other zero width characters stay rejected
+        assert_eq!(
+            rules.validate("GM\u{200C}world"),
+            Err(GmError::NameHasZeroWidthCharacter)
+        );
+    }
+
+    #[test]
+    fn avatar_uri_with_supported_scheme_is_valid() {
+        for uri in ["https://example.com/gm.png", "ipfs://bafy", "ar://abc"] {
+            assert_eq!(validate_avatar_uri(uri), Ok(()), "{}", uri);
+        }
+    }
+
+    #[test]
+    fn avatar_uri_is_rejected() {
+        let too_long = format!("https://{}", "a".repeat(Avatar::MAX_URI_LEN));
+        for uri in [
+            "http://example.com/gm.png",
+            "https://",
+            "javascript:alert(1)",
+            "https://example.com/g m.png",
+            "https://exаmple.com",
+            too_long.as_str(),
+        ] {
+            assert_eq!(
+                validate_avatar_uri(uri),
+                Err(GmError::InvalidAvatarUri),
+                "{}",
+                uri
+            );
+        }
+    }
+}
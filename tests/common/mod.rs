@@ -0,0 +1,399 @@
+// Each test crate uses only some of the helpers
+#![allow(dead_code)]
+
+use gm_program::{
+    error::GmError,
+    instruction::GmInstruction,
+    processor::process_instruction,
+    rate_limit::{RateLimits, TokenBucket},
+    state::{
+        find_config_address, find_greeter_record_address, find_greeting_address,
+        find_inbox_address, find_inbox_page_address, find_outbox_address, find_outbox_page_address,
+        find_profile_address, find_sender_record_address, find_treasury_address, Config,
+        ConfigSettings, GreeterRecord, GreetingAccount, LegacyGreeting, Outbox, OutboxPage,
+        Profile, ProgramAccount,
+    },
+};
+use solana_program_test::{processor, ProgramTest, ProgramTestBanksClientExt, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    clock::Clock,
+    hash::Hash,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::{Transaction, TransactionError},
+};
+
+/// Lamports given to the wallets the tests create
+pub const WALLET_LAMPORTS: u64 = 10_000_000_000;
+
+pub fn program_id() -> Pubkey {
+    Pubkey::new_from_array([7; 32])
+}
+
+pub fn program_test() -> ProgramTest {
+    ProgramTest::new("gm_program", program_id(), processor!(process_instruction))
+}
+
+/// Add a system account holding lamports for a new wallet
+pub fn add_wallet(program_test: &mut ProgramTest) -> Keypair {
+    let wallet = Keypair::new();
+    program_test.add_account(
+        wallet.pubkey(),
+        Account {
+            lamports: WALLET_LAMPORTS,
+            owner: system_program::id(),
+            ..Account::default()
+        },
+    );
+    wallet
+}
+
+/// Add the program data account the upgradeable loader would have written on deploy,
+/// making `authority` the upgrade authority of the program
+pub fn add_program_data(program_test: &mut ProgramTest, authority: &Pubkey) {
+    let (program_data_address, _) =
+        Pubkey::find_program_address(&[program_id().as_ref()], &bpf_loader_upgradeable::id());
+    let data = bincode::serialize(&UpgradeableLoaderState::ProgramData {
+        slot: 0,
+        upgrade_authority_address: Some(*authority),
+    })
+    .unwrap();
+    program_test.add_account(
+        program_data_address,
+        Account {
+            lamports: 1_000_000_000,
+            data,
+            owner: bpf_loader_upgradeable::id(),
+            ..Account::default()
+        },
+    );
+}
+
+/// Add an account owned by the program holding `state` in `space` bytes, as if the program
+/// had created it. Outside the BPF build, solana-program-test cannot resize accounts in
+/// the system program calls the program makes to create them.
+pub fn add_state<T: ProgramAccount>(
+    program_test: &mut ProgramTest,
+    address: Pubkey,
+    state: &T,
+    space: usize,
+) {
+    let mut data = vec![0; space];
+    state.pack(&mut data).unwrap();
+    program_test.add_account(
+        address,
+        Account {
+            lamports: Rent::default().minimum_balance(space),
+            data,
+            owner: program_id(),
+            ..Account::default()
+        },
+    );
+}
+
+/// Add the accounts `SayGm` creates on the first GM of `greeter` to `greeting`, with the
+/// first page of its outbox
+pub fn add_greeter_accounts(program_test: &mut ProgramTest, greeting: &Pubkey, greeter: &Pubkey) {
+    let program_id = program_id();
+    let (record_address, _) = find_greeter_record_address(&program_id, greeting, greeter);
+    let record = GreeterRecord {
+        greeting: *greeting,
+        greeter: *greeter,
+        gm_count: 0,
+        last_gm_slot: 0,
+    };
+    add_state(program_test, record_address, &record, GreeterRecord::LEN);
+
+    let (outbox_address, _) = find_outbox_address(&program_id, greeter);
+    let outbox = Outbox {
+        page_count: 1,
+        ..Outbox::new(*greeter, TokenBucket::new(&RateLimits::default(), 0))
+    };
+    add_state(program_test, outbox_address, &outbox, Outbox::LEN);
+    let (page_address, _) = find_outbox_page_address(&program_id, greeter, 0);
+    let page = OutboxPage {
+        sender: *greeter,
+        index: 0,
+        entries: Vec::new(),
+    };
+    add_state(program_test, page_address, &page, OutboxPage::LEN);
+
+    let (profile_address, _) = find_profile_address(&program_id, greeter);
+    add_state(
+        program_test,
+        profile_address,
+        &Profile::new(*greeter),
+        Profile::LEN,
+    );
+}
+
+/// Add the greeting account `Initialize` creates for `name`, with `authority` as its payer
+pub fn add_greeting(program_test: &mut ProgramTest, authority: &Pubkey, name: &str) -> Pubkey {
+    let (address, _) = find_greeting_address(&program_id(), authority, name);
+    let greeting = GreetingAccount::new(*authority, name.to_string(), 0, 0);
+    add_state(program_test, address, &greeting, GreetingAccount::space(0));
+    address
+}
+
+/// Add a greeting account of the legacy layout, created by `creator` with `seed` and
+/// `space` bytes
+pub fn add_legacy_greeting(
+    program_test: &mut ProgramTest,
+    creator: &Pubkey,
+    seed: &str,
+    name: &str,
+    space: usize,
+) -> Pubkey {
+    let address = Pubkey::create_with_seed(creator, seed, &program_id()).unwrap();
+    let mut data = vec![0; space];
+    LegacyGreeting {
+        name: name.to_string(),
+    }
+    .pack(&mut data)
+    .unwrap();
+    program_test.add_account(
+        address,
+        Account {
+            lamports: Rent::default().minimum_balance(space),
+            data,
+            owner: program_id(),
+            ..Account::default()
+        },
+    );
+    address
+}
+
+/// Add an initialized config with `admin` as its admin, and the treasury created with it
+pub fn add_config(program_test: &mut ProgramTest, admin: &Pubkey, paused_instructions: u64) {
+    let config = Config {
+        admin: *admin,
+        paused_instructions,
+        ..Config::uninitialized()
+    };
+    add_config_state(program_test, &config);
+}
+
+/// Add `config` at the config address, and the treasury created with it
+pub fn add_config_state(program_test: &mut ProgramTest, config: &Config) {
+    let program_id = program_id();
+    let (config_address, _) = find_config_address(&program_id);
+    add_state(program_test, config_address, config, Config::LEN);
+
+    let (treasury_address, _) = find_treasury_address(&program_id);
+    program_test.add_account(
+        treasury_address,
+        Account {
+            lamports: Rent::default().minimum_balance(0),
+            owner: program_id,
+            ..Account::default()
+        },
+    );
+}
+
+/// Typed instruction taking `accounts` followed by the config
+pub fn instruction(gm_instruction: GmInstruction, mut accounts: Vec<AccountMeta>) -> Instruction {
+    let (config_address, _) = find_config_address(&program_id());
+    accounts.push(AccountMeta::new_readonly(config_address, false));
+    Instruction::new_with_bytes(program_id(), &gm_instruction.pack().unwrap(), accounts)
+}
+
+/// Typed instruction taking `accounts` followed by the config, which it changes
+pub fn config_instruction(
+    gm_instruction: GmInstruction,
+    mut accounts: Vec<AccountMeta>,
+) -> Instruction {
+    let (config_address, _) = find_config_address(&program_id());
+    accounts.push(AccountMeta::new(config_address, false));
+    Instruction::new_with_bytes(program_id(), &gm_instruction.pack().unwrap(), accounts)
+}
+
+pub fn initialize(payer: &Pubkey, name: &str) -> Instruction {
+    let (greeting_address, _) = find_greeting_address(&program_id(), payer, name);
+    instruction(
+        GmInstruction::Initialize {
+            name: name.to_string(),
+        },
+        vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(greeting_address, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+/// `SayGm` of a greeter whose outbox is on its first page
+pub fn say_gm(greeting: &Pubkey, greeter: &Pubkey) -> Instruction {
+    let program_id = program_id();
+    let (record_address, _) = find_greeter_record_address(&program_id, greeting, greeter);
+    let (outbox_address, _) = find_outbox_address(&program_id, greeter);
+    let (outbox_page_address, _) = find_outbox_page_address(&program_id, greeter, 0);
+    let (profile_address, _) = find_profile_address(&program_id, greeter);
+    let (treasury_address, _) = find_treasury_address(&program_id);
+    instruction(
+        GmInstruction::SayGm,
+        vec![
+            AccountMeta::new(*greeting, false),
+            AccountMeta::new(*greeter, true),
+            AccountMeta::new(record_address, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(outbox_address, false),
+            AccountMeta::new(outbox_page_address, false),
+            AccountMeta::new(profile_address, false),
+            AccountMeta::new(treasury_address, false),
+        ],
+    )
+}
+
+/// `SayGmTo` to a recipient whose inbox is on its first page, from a sender whose outbox is
+/// on its first page
+pub fn say_gm_to(sender: &Pubkey, recipient: &Pubkey) -> Instruction {
+    let program_id = program_id();
+    let (inbox_address, _) = find_inbox_address(&program_id, recipient);
+    let (page_address, _) = find_inbox_page_address(&program_id, recipient, 0);
+    let (outbox_address, _) = find_outbox_address(&program_id, sender);
+    let (outbox_page_address, _) = find_outbox_page_address(&program_id, sender, 0);
+    let (profile_address, _) = find_profile_address(&program_id, sender);
+    let (treasury_address, _) = find_treasury_address(&program_id);
+    let (record_address, _) = find_sender_record_address(&program_id, recipient, sender);
+    instruction(
+        GmInstruction::SayGmTo,
+        vec![
+            AccountMeta::new(*sender, true),
+            AccountMeta::new_readonly(*recipient, false),
+            AccountMeta::new(inbox_address, false),
+            AccountMeta::new(page_address, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(outbox_address, false),
+            AccountMeta::new(outbox_page_address, false),
+            AccountMeta::new(profile_address, false),
+            AccountMeta::new(treasury_address, false),
+            AccountMeta::new(record_address, false),
+        ],
+    )
+}
+
+pub fn update(greeting: &Pubkey, authority: &Pubkey, name: &str) -> Instruction {
+    instruction(
+        GmInstruction::Update {
+            name: name.to_string(),
+        },
+        vec![
+            AccountMeta::new(*greeting, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+    )
+}
+
+/// `Migrate` of the legacy account `payer` created with `seed`, moving it to the greeting
+/// address of `name` when it is too small
+pub fn migrate(payer: &Pubkey, seed: &str, name: &str) -> Instruction {
+    let program_id = program_id();
+    let legacy_address = Pubkey::create_with_seed(payer, seed, &program_id).unwrap();
+    let (greeting_address, _) = find_greeting_address(&program_id, payer, name);
+    instruction(
+        GmInstruction::Migrate {
+            seed: seed.to_string(),
+        },
+        vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(legacy_address, false),
+            AccountMeta::new(greeting_address, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn initialize_config(authority: &Pubkey, settings: ConfigSettings) -> Instruction {
+    let program_id = program_id();
+    let (program_data_address, _) =
+        Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+    let (treasury_address, _) = find_treasury_address(&program_id);
+    config_instruction(
+        GmInstruction::InitializeConfig { settings },
+        vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(program_data_address, false),
+            AccountMeta::new(treasury_address, false),
+        ],
+    )
+}
+
+pub fn set_paused_instructions(admin: &Pubkey, paused_instructions: u64) -> Instruction {
+    config_instruction(
+        GmInstruction::SetPausedInstructions {
+            paused_instructions,
+        },
+        vec![AccountMeta::new_readonly(*admin, true)],
+    )
+}
+
+/// Send the instructions in one transaction paid by the payer of the context, on a new
+/// blockhash so that identical transactions can be sent in a row
+pub async fn process(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), TransactionError> {
+    let blockhash = new_blockhash(context).await;
+    process_with_blockhash(context, instructions, signers, blockhash).await
+}
+
+/// Wait for a blockhash other than the last one of the context
+pub async fn new_blockhash(context: &mut ProgramTestContext) -> Hash {
+    let (blockhash, _) = context
+        .banks_client
+        .get_new_blockhash(&context.last_blockhash)
+        .await
+        .unwrap();
+    context.last_blockhash = blockhash;
+    blockhash
+}
+
+/// Send the instructions in one transaction paid by the payer of the context on `blockhash`.
+/// Once warped, the banks server only accepts blockhashes from before the warp.
+pub async fn process_with_blockhash(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+    blockhash: Hash,
+) -> Result<(), TransactionError> {
+    let mut transaction = Transaction::new_with_payer(instructions, Some(&context.payer.pubkey()));
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    transaction.sign(&all_signers, blockhash);
+
+    context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .map_err(|e| e.unwrap())
+}
+
+/// Check that a transaction failed on the given error of the program
+pub fn assert_gm_error(result: Result<(), TransactionError>, expected: GmError) {
+    match result {
+        Err(TransactionError::InstructionError(_, InstructionError::Custom(code))) => {
+            assert_eq!(code, expected as u32, "expected {:?}", expected)
+        }
+        other => panic!("expected {:?}, got {:?}", expected, other),
+    }
+}
+
+/// Read a program account, `None` once it is closed
+pub async fn get_state<T: ProgramAccount>(
+    context: &mut ProgramTestContext,
+    address: Pubkey,
+) -> Option<T> {
+    let account = context.banks_client.get_account(address).await.unwrap()?;
+    Some(T::unpack(&account.data).unwrap())
+}
+
+pub async fn get_clock(context: &mut ProgramTestContext) -> Clock {
+    context.banks_client.get_sysvar::<Clock>().await.unwrap()
+}
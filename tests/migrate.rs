@@ -0,0 +1,266 @@
+mod common;
+
+use common::*;
+use gm_program::{
+    error::GmError,
+    state::{find_greeting_address, GreetingAccount},
+};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::{pubkey::Pubkey, signature::Signer};
+
+const SEED: &str = "greeting";
+
+/// Legacy account too small for the current layout
+const SMALL_LEGACY_SPACE: usize = 64;
+
+async fn assert_migrated(
+    context: &mut ProgramTestContext,
+    address: Pubkey,
+    authority: &Pubkey,
+    name: &str,
+) {
+    let greeting: GreetingAccount = get_state(context, address).await.unwrap();
+    assert_eq!(greeting.authority, *authority);
+    assert_eq!(greeting.name, name);
+    assert_eq!(greeting.gm_count, 0);
+    assert_eq!(greeting.first_greeter, None);
+}
+
+#[tokio::test]
+async fn migrate_in_place() {
+    let mut program_test = program_test();
+    let payer = add_wallet(&mut program_test);
+    let legacy = add_legacy_greeting(
+        &mut program_test,
+        &payer.pubkey(),
+        SEED,
+        "gm",
+        GreetingAccount::space(0),
+    );
+    let mut context = program_test.start_with_context().await;
+
+    process(
+        &mut context,
+        &[migrate(&payer.pubkey(), SEED, "gm")],
+        &[&payer],
+    )
+    .await
+    .unwrap();
+    assert_migrated(&mut context, legacy, &payer.pubkey(), "gm").await;
+
+    // Nothing is created at the greeting address when the account is rewritten in place
+    let (address, _) = find_greeting_address(&program_id(), &payer.pubkey(), "gm");
+    assert!(context
+        .banks_client
+        .get_account(address)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn migrate_twice_succeeds_without_changes() {
+    let mut program_test = program_test();
+    let payer = add_wallet(&mut program_test);
+    let legacy = add_legacy_greeting(
+        &mut program_test,
+        &payer.pubkey(),
+        SEED,
+        "gm",
+        GreetingAccount::space(0),
+    );
+    let mut context = program_test.start_with_context().await;
+
+    process(
+        &mut context,
+        &[migrate(&payer.pubkey(), SEED, "gm")],
+        &[&payer],
+    )
+    .await
+    .unwrap();
+    let migrated = context.banks_client.get_account(legacy).await.unwrap();
+
+    process(
+        &mut context,
+        &[migrate(&payer.pubkey(), SEED, "gm")],
+        &[&payer],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        context.banks_client.get_account(legacy).await.unwrap(),
+        migrated
+    );
+}
+
+#[tokio::test]
+async fn migrate_keeps_names_the_current_rules_reject() {
+    let long_name = "g".repeat(GreetingAccount::MAX_NAME_LEN + 10);
+    let names = ["GM\nworld", long_name.as_str()];
+
+    let mut program_test = program_test();
+    let payer = add_wallet(&mut program_test);
+    let mut legacy_accounts = Vec::new();
+    for (index, name) in names.iter().enumerate() {
+        let seed = format!("{}{}", SEED, index);
+        // Room for the name even when it is longer than allowed now
+        let space = GreetingAccount::space(0) + name.len();
+        let legacy = add_legacy_greeting(&mut program_test, &payer.pubkey(), &seed, name, space);
+        legacy_accounts.push((seed, legacy));
+    }
+    let mut context = program_test.start_with_context().await;
+
+    for (name, (seed, legacy)) in names.iter().zip(legacy_accounts) {
+        process(
+            &mut context,
+            &[migrate(&payer.pubkey(), &seed, name)],
+            &[&payer],
+        )
+        .await
+        .unwrap();
+        assert_migrated(&mut context, legacy, &payer.pubkey(), name).await;
+    }
+}
+
+#[tokio::test]
+async fn update_replaces_a_migrated_name_the_rules_reject() {
+    let mut program_test = program_test();
+    let payer = add_wallet(&mut program_test);
+    let legacy = add_legacy_greeting(
+        &mut program_test,
+        &payer.pubkey(),
+        SEED,
+        "GM\nworld",
+        GreetingAccount::space(0),
+    );
+    let mut context = program_test.start_with_context().await;
+
+    process(
+        &mut context,
+        &[migrate(&payer.pubkey(), SEED, "GM\nworld")],
+        &[&payer],
+    )
+    .await
+    .unwrap();
+    process(
+        &mut context,
+        &[update(&legacy, &payer.pubkey(), "GM world")],
+        &[&payer],
+    )
+    .await
+    .unwrap();
+    assert_migrated(&mut context, legacy, &payer.pubkey(), "GM world").await;
+
+    // Once valid, the name is bound to its normalized form like any other
+    assert_gm_error(
+        process(
+            &mut context,
+            &[update(&legacy, &payer.pubkey(), "GN world")],
+            &[&payer],
+        )
+        .await,
+        GmError::NameChangesAddress,
+    );
+}
+
+#[tokio::test]
+async fn migrate_with_the_wrong_seed_fails() {
+    let mut program_test = program_test();
+    let payer = add_wallet(&mut program_test);
+    let legacy = add_legacy_greeting(
+        &mut program_test,
+        &payer.pubkey(),
+        SEED,
+        "gm",
+        GreetingAccount::space(0),
+    );
+    let mut context = program_test.start_with_context().await;
+
+    let mut instruction = migrate(&payer.pubkey(), "other", "gm");
+    instruction.accounts[1].pubkey = legacy;
+    assert_gm_error(
+        process(&mut context, &[instruction], &[&payer]).await,
+        GmError::InvalidAccountAddress,
+    );
+}
+
+#[tokio::test]
+async fn migrate_by_another_payer_fails() {
+    let mut program_test = program_test();
+    let creator = add_wallet(&mut program_test);
+    let other = add_wallet(&mut program_test);
+    let legacy = add_legacy_greeting(
+        &mut program_test,
+        &creator.pubkey(),
+        SEED,
+        "gm",
+        GreetingAccount::space(0),
+    );
+    let mut context = program_test.start_with_context().await;
+
+    let mut instruction = migrate(&other.pubkey(), SEED, "gm");
+    instruction.accounts[1].pubkey = legacy;
+    assert_gm_error(
+        process(&mut context, &[instruction], &[&other]).await,
+        GmError::InvalidAccountAddress,
+    );
+}
+
+#[tokio::test]
+async fn migrate_of_a_missing_legacy_account_fails_on_another_greeting() {
+    let mut program_test = program_test();
+    let payer = add_wallet(&mut program_test);
+    let other = add_wallet(&mut program_test);
+    let greeting = add_greeting(&mut program_test, &other.pubkey(), "gm");
+    let mut context = program_test.start_with_context().await;
+
+    // No legacy account was ever created with this seed
+    let mut instruction = migrate(&payer.pubkey(), SEED, "gm");
+    instruction.accounts[2].pubkey = greeting;
+    assert_gm_error(
+        process(&mut context, &[instruction], &[&payer]).await,
+        GmError::IncorrectOwner,
+    );
+}
+
+// Creating the new account needs a BPF build of the program, run with `cargo test-bpf`
+#[tokio::test]
+#[cfg_attr(not(feature = "test-bpf"), ignore)]
+async fn migrate_moves_a_small_account_and_closes_it() {
+    let mut program_test = program_test();
+    let payer = add_wallet(&mut program_test);
+    let legacy = add_legacy_greeting(
+        &mut program_test,
+        &payer.pubkey(),
+        SEED,
+        "gm",
+        SMALL_LEGACY_SPACE,
+    );
+    let mut context = program_test.start_with_context().await;
+    let (address, _) = find_greeting_address(&program_id(), &payer.pubkey(), "gm");
+
+    process(
+        &mut context,
+        &[migrate(&payer.pubkey(), SEED, "gm")],
+        &[&payer],
+    )
+    .await
+    .unwrap();
+    assert_migrated(&mut context, address, &payer.pubkey(), "gm").await;
+    assert!(context
+        .banks_client
+        .get_account(legacy)
+        .await
+        .unwrap()
+        .is_none());
+
+    // The moved account is recognized once the legacy one is gone
+    process(
+        &mut context,
+        &[migrate(&payer.pubkey(), SEED, "gm")],
+        &[&payer],
+    )
+    .await
+    .unwrap();
+    assert_migrated(&mut context, address, &payer.pubkey(), "gm").await;
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use common::*;
+use gm_program::{
+    error::GmError,
+    instruction::GmInstruction,
+    state::{
+        find_name_record_address, find_reverse_record_address, Config, ConfigSettings,
+        GreetingAccount, NameRecord,
+    },
+};
+use solana_program_test::tokio;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    signature::Signer,
+    system_program,
+};
+
+fn register_name(owner: &Pubkey, name: &str) -> Instruction {
+    let program_id = program_id();
+    let (record_address, _) = find_name_record_address(&program_id, name);
+    let (reverse_address, _) = find_reverse_record_address(&program_id, owner);
+    instruction(
+        GmInstruction::RegisterName {
+            name: name.to_string(),
+        },
+        vec![
+            AccountMeta::new(*owner, true),
+            AccountMeta::new(record_address, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(reverse_address, false),
+        ],
+    )
+}
+
+#[tokio::test]
+async fn register_name_longer_than_max_name_len_fails() {
+    let mut program_test = program_test();
+    let owner = add_wallet(&mut program_test);
+    let config = Config {
+        settings: ConfigSettings {
+            max_name_len: 8,
+            ..ConfigSettings::default()
+        },
+        ..Config::uninitialized()
+    };
+    add_config_state(&mut program_test, &config);
+    let mut context = program_test.start_with_context().await;
+
+    assert_gm_error(
+        process(
+            &mut context,
+            &[register_name(&owner.pubkey(), &"g".repeat(9))],
+            &[&owner],
+        )
+        .await,
+        GmError::NameTooLong,
+    );
+}
+
+// Creating the name record needs a BPF build of the program, run with `cargo test-bpf`
+#[tokio::test]
+#[cfg_attr(not(feature = "test-bpf"), ignore)]
+async fn register_name_longer_than_a_seed() {
+    let mut program_test = program_test();
+    let owner = add_wallet(&mut program_test);
+    let mut context = program_test.start_with_context().await;
+
+    let name = "g".repeat(GreetingAccount::MAX_NAME_LEN);
+    process(
+        &mut context,
+        &[register_name(&owner.pubkey(), &name.to_uppercase())],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+
+    let (record_address, _) = find_name_record_address(&program_id(), &name);
+    let record: NameRecord = get_state(&mut context, record_address).await.unwrap();
+    assert_eq!(record.owner, owner.pubkey());
+    assert_eq!(record.name, name);
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use common::*;
+use gm_program::{
+    error::GmError,
+    rate_limit::{RateLimits, TokenBucket},
+    state::{
+        find_outbox_address, find_outbox_page_address, Outbox, OutboxEntry, OutboxPage,
+        ProgramAccount,
+    },
+};
+use solana_program_test::{tokio, ProgramTest};
+use solana_sdk::{pubkey::Pubkey, signature::Signer};
+
+/// Replace the outbox of `sender` with one that said `gm_count` GMs, the last page holding
+/// the GMs said since the previous one filled up
+fn add_outbox(program_test: &mut ProgramTest, sender: &Pubkey, gm_count: u64) {
+    let program_id = program_id();
+    let capacity = OutboxPage::CAPACITY as u64;
+    let page_count = gm_count.div_ceil(capacity).max(1) as u32;
+    let (outbox_address, _) = find_outbox_address(&program_id, sender);
+    let outbox = Outbox {
+        gm_count,
+        page_count,
+        ..Outbox::new(*sender, TokenBucket::new(&RateLimits::default(), 0))
+    };
+    add_state(program_test, outbox_address, &outbox, Outbox::LEN);
+
+    let index = page_count - 1;
+    let (page_address, _) = find_outbox_page_address(&program_id, sender, index);
+    let entry = OutboxEntry {
+        recipient: Pubkey::new_unique(),
+        unix_timestamp: 0,
+    };
+    let page = OutboxPage {
+        sender: *sender,
+        index,
+        entries: vec![entry; (gm_count - index as u64 * capacity) as usize],
+    };
+    add_state(program_test, page_address, &page, OutboxPage::LEN);
+}
+
+#[tokio::test]
+async fn gm_is_appended_to_the_current_outbox_page() {
+    let program_id = program_id();
+    let mut program_test = program_test();
+    let greeter = add_wallet(&mut program_test);
+    let greeting = add_greeting(&mut program_test, &greeter.pubkey(), "gm");
+    add_greeter_accounts(&mut program_test, &greeting, &greeter.pubkey());
+    let mut context = program_test.start_with_context().await;
+
+    // Past the cooldown of the greeter record added at slot zero, on a blockhash from before
+    let blockhash = new_blockhash(&mut context).await;
+    context.warp_to_slot(1_000).unwrap();
+    process_with_blockhash(
+        &mut context,
+        &[say_gm(&greeting, &greeter.pubkey())],
+        &[&greeter],
+        blockhash,
+    )
+    .await
+    .unwrap();
+
+    let (outbox_address, _) = find_outbox_address(&program_id, &greeter.pubkey());
+    let outbox: Outbox = get_state(&mut context, outbox_address).await.unwrap();
+    assert_eq!(outbox.gm_count, 1);
+    assert_eq!(outbox.page_count, 1);
+    let (page_address, _) = find_outbox_page_address(&program_id, &greeter.pubkey(), 0);
+    let page: OutboxPage = get_state(&mut context, page_address).await.unwrap();
+    assert_eq!(page.entries.len(), 1);
+    assert_eq!(page.entries[0].recipient, greeting);
+}
+
+#[tokio::test]
+async fn gm_to_another_outbox_page_fails() {
+    let mut program_test = program_test();
+    let greeter = add_wallet(&mut program_test);
+    let greeting = add_greeting(&mut program_test, &greeter.pubkey(), "gm");
+    add_greeter_accounts(&mut program_test, &greeting, &greeter.pubkey());
+    // The first page is full, so the GM goes to the second one
+    add_outbox(
+        &mut program_test,
+        &greeter.pubkey(),
+        OutboxPage::CAPACITY as u64,
+    );
+    let mut context = program_test.start_with_context().await;
+
+    let blockhash = new_blockhash(&mut context).await;
+    context.warp_to_slot(1_000).unwrap();
+    assert_gm_error(
+        process_with_blockhash(
+            &mut context,
+            &[say_gm(&greeting, &greeter.pubkey())],
+            &[&greeter],
+            blockhash,
+        )
+        .await,
+        GmError::InvalidAccountAddress,
+    );
+}
+
+// Creating the next page needs a BPF build of the program, run with `cargo test-bpf`
+#[tokio::test]
+#[cfg_attr(not(feature = "test-bpf"), ignore)]
+async fn gm_starts_the_next_outbox_page_once_one_is_full() {
+    let program_id = program_id();
+    let mut program_test = program_test();
+    let greeter = add_wallet(&mut program_test);
+    let greeting = add_greeting(&mut program_test, &greeter.pubkey(), "gm");
+    add_greeter_accounts(&mut program_test, &greeting, &greeter.pubkey());
+    add_outbox(
+        &mut program_test,
+        &greeter.pubkey(),
+        OutboxPage::CAPACITY as u64,
+    );
+    let mut context = program_test.start_with_context().await;
+
+    let (page_address, _) = find_outbox_page_address(&program_id, &greeter.pubkey(), 1);
+    let mut instruction = say_gm(&greeting, &greeter.pubkey());
+    instruction.accounts[5].pubkey = page_address;
+    let blockhash = new_blockhash(&mut context).await;
+    context.warp_to_slot(1_000).unwrap();
+    process_with_blockhash(&mut context, &[instruction], &[&greeter], blockhash)
+        .await
+        .unwrap();
+
+    let (outbox_address, _) = find_outbox_address(&program_id, &greeter.pubkey());
+    let outbox: Outbox = get_state(&mut context, outbox_address).await.unwrap();
+    assert_eq!(outbox.gm_count, OutboxPage::CAPACITY as u64 + 1);
+    assert_eq!(outbox.page_count, 2);
+    let page: OutboxPage = get_state(&mut context, page_address).await.unwrap();
+    assert_eq!(page.index, 1);
+    assert_eq!(page.entries.len(), 1);
+    assert_eq!(page.entries[0].recipient, greeting);
+}
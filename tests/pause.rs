@@ -0,0 +1,294 @@
+mod common;
+
+use borsh::BorshSerialize;
+use common::*;
+use gm_program::{
+    error::GmError,
+    instruction::GmInstruction,
+    state::{
+        find_config_address, find_name_record_address, find_profile_address,
+        find_reverse_record_address, find_treasury_address, ConfigSettings, GreetingAccount,
+        LegacyGreeting, NameRecord, Profile, ProgramAccount,
+    },
+};
+use solana_program_test::tokio;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    signature::Signer,
+};
+
+/// Legacy payload saying GM to `name`, as sent by clients predating `GmInstruction`
+fn legacy_say_gm(accounts: Vec<AccountMeta>, name: &str) -> Instruction {
+    let data = LegacyGreeting {
+        name: name.to_string(),
+    }
+    .try_to_vec()
+    .unwrap();
+    Instruction::new_with_bytes(program_id(), &data, accounts)
+}
+
+/// One instruction of every variant that can be paused
+fn pausable_instructions() -> Vec<GmInstruction> {
+    let name = "gm".to_string();
+    vec![
+        GmInstruction::Initialize { name: name.clone() },
+        GmInstruction::SayGm,
+        GmInstruction::Update { name: name.clone() },
+        GmInstruction::Migrate { seed: name.clone() },
+        GmInstruction::InitializeWithHistory {
+            name: name.clone(),
+            history_capacity: 1,
+        },
+        GmInstruction::SayGmTo,
+        GmInstruction::SetUtcOffset {
+            utc_offset_minutes: 0,
+        },
+        GmInstruction::CreateProfile {
+            display_name: name.clone(),
+            avatar: None,
+            bio: String::new(),
+            utc_offset_minutes: 0,
+        },
+        GmInstruction::UpdateProfile {
+            display_name: name.clone(),
+            avatar: None,
+            bio: String::new(),
+            utc_offset_minutes: 0,
+        },
+        GmInstruction::RegisterName { name: name.clone() },
+        GmInstruction::TransferName {
+            new_owner: Pubkey::new_unique(),
+        },
+        GmInstruction::SayGmToName { name },
+        GmInstruction::SetPrimaryName,
+    ]
+}
+
+#[tokio::test]
+async fn paused_instructions_fail() {
+    let mut program_test = program_test();
+    let admin = add_wallet(&mut program_test);
+    add_config(&mut program_test, &admin.pubkey(), 0);
+    let mut context = program_test.start_with_context().await;
+
+    for gm_instruction in pausable_instructions() {
+        let pause_bit = gm_instruction.pause_bit().unwrap();
+        process(
+            &mut context,
+            &[set_paused_instructions(&admin.pubkey(), pause_bit)],
+            &[&admin],
+        )
+        .await
+        .unwrap();
+
+        // The pause is checked before any of the accounts of the instruction
+        assert_gm_error(
+            process(&mut context, &[instruction(gm_instruction, vec![])], &[]).await,
+            GmError::Paused,
+        );
+    }
+}
+
+#[tokio::test]
+async fn pausing_say_gm_halts_legacy_gms() {
+    let mut program_test = program_test();
+    let admin = add_wallet(&mut program_test);
+    let creator = add_wallet(&mut program_test);
+    let pause_bit = GmInstruction::SayGm.pause_bit().unwrap();
+    add_config(&mut program_test, &admin.pubkey(), pause_bit);
+    let legacy = add_legacy_greeting(&mut program_test, &creator.pubkey(), "greeting", "gm", 64);
+    let mut context = program_test.start_with_context().await;
+
+    let (config_address, _) = find_config_address(&program_id());
+    let legacy_say_gm = legacy_say_gm(
+        vec![
+            AccountMeta::new(legacy, false),
+            AccountMeta::new_readonly(config_address, false),
+        ],
+        "gm",
+    );
+    assert_gm_error(
+        process(&mut context, std::slice::from_ref(&legacy_say_gm), &[]).await,
+        GmError::Paused,
+    );
+
+    process(
+        &mut context,
+        &[set_paused_instructions(&admin.pubkey(), 0)],
+        &[&admin],
+    )
+    .await
+    .unwrap();
+    process(&mut context, &[legacy_say_gm], &[]).await.unwrap();
+}
+
+#[tokio::test]
+async fn legacy_gms_without_the_config_cannot_be_paused() {
+    let mut program_test = program_test();
+    let admin = add_wallet(&mut program_test);
+    let creator = add_wallet(&mut program_test);
+    let pause_bit = GmInstruction::SayGm.pause_bit().unwrap();
+    add_config(&mut program_test, &admin.pubkey(), pause_bit);
+    let legacy = add_legacy_greeting(&mut program_test, &creator.pubkey(), "greeting", "gm", 64);
+    let mut context = program_test.start_with_context().await;
+
+    // Old clients send the greeting account alone
+    process(
+        &mut context,
+        &[legacy_say_gm(vec![AccountMeta::new(legacy, false)], "gn")],
+        &[],
+    )
+    .await
+    .unwrap();
+    let account = context
+        .banks_client
+        .get_account(legacy)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        LegacyGreeting::unpack(&account.data).unwrap(),
+        LegacyGreeting {
+            name: "gn".to_string(),
+        }
+    );
+}
+
+#[tokio::test]
+async fn closing_and_config_instructions_run_with_everything_paused() {
+    let program_id = program_id();
+    let mut program_test = program_test();
+    let admin = add_wallet(&mut program_test);
+    let new_admin = add_wallet(&mut program_test);
+    let wallet = add_wallet(&mut program_test);
+    add_program_data(&mut program_test, &admin.pubkey());
+    add_config(&mut program_test, &admin.pubkey(), u64::MAX);
+
+    let greeting = add_greeting(&mut program_test, &wallet.pubkey(), "gm");
+    let (profile_address, _) = find_profile_address(&program_id, &wallet.pubkey());
+    add_state(
+        &mut program_test,
+        profile_address,
+        &Profile::new(wallet.pubkey()),
+        Profile::LEN,
+    );
+    let (record_address, _) = find_name_record_address(&program_id, "gm");
+    let record = NameRecord {
+        owner: wallet.pubkey(),
+        registered_at: 0,
+        name: "gm".to_string(),
+    };
+    add_state(&mut program_test, record_address, &record, NameRecord::LEN);
+    let mut context = program_test.start_with_context().await;
+
+    let (reverse_address, _) = find_reverse_record_address(&program_id, &wallet.pubkey());
+    let (treasury_address, _) = find_treasury_address(&program_id);
+    let wallet_instructions = vec![
+        instruction(
+            GmInstruction::Close,
+            vec![
+                AccountMeta::new(greeting, false),
+                AccountMeta::new_readonly(wallet.pubkey(), true),
+                AccountMeta::new(wallet.pubkey(), false),
+            ],
+        ),
+        instruction(
+            GmInstruction::CloseProfile,
+            vec![
+                AccountMeta::new_readonly(wallet.pubkey(), true),
+                AccountMeta::new(profile_address, false),
+                AccountMeta::new(wallet.pubkey(), false),
+            ],
+        ),
+        instruction(
+            GmInstruction::ReleaseName,
+            vec![
+                AccountMeta::new_readonly(wallet.pubkey(), true),
+                AccountMeta::new(record_address, false),
+                AccountMeta::new(wallet.pubkey(), false),
+                AccountMeta::new(reverse_address, false),
+            ],
+        ),
+    ];
+    for wallet_instruction in wallet_instructions {
+        process(&mut context, &[wallet_instruction], &[&wallet])
+            .await
+            .unwrap();
+    }
+    assert!(get_state::<GreetingAccount>(&mut context, greeting)
+        .await
+        .is_none());
+    assert!(get_state::<Profile>(&mut context, profile_address)
+        .await
+        .is_none());
+    assert!(get_state::<NameRecord>(&mut context, record_address)
+        .await
+        .is_none());
+
+    let admin_only = |gm_instruction| {
+        config_instruction(
+            gm_instruction,
+            vec![AccountMeta::new_readonly(admin.pubkey(), true)],
+        )
+    };
+    let admin_instructions = vec![
+        admin_only(GmInstruction::UpdateConfig {
+            settings: ConfigSettings::default(),
+        }),
+        admin_only(GmInstruction::SetFeeExempt {
+            wallet: wallet.pubkey(),
+            exempt: true,
+        }),
+        admin_only(GmInstruction::CancelProposals),
+        instruction(
+            GmInstruction::WithdrawTreasury { lamports: 0 },
+            vec![
+                AccountMeta::new_readonly(admin.pubkey(), true),
+                AccountMeta::new(treasury_address, false),
+                AccountMeta::new(admin.pubkey(), false),
+            ],
+        ),
+        admin_only(GmInstruction::ProposeAdmin {
+            new_admin: new_admin.pubkey(),
+        }),
+    ];
+    for admin_instruction in admin_instructions {
+        process(&mut context, &[admin_instruction], &[&admin])
+            .await
+            .unwrap();
+    }
+
+    process(
+        &mut context,
+        &[config_instruction(
+            GmInstruction::AcceptAdmin,
+            vec![AccountMeta::new_readonly(new_admin.pubkey(), true)],
+        )],
+        &[&new_admin],
+    )
+    .await
+    .unwrap();
+
+    // Initializing again fails because the config exists, not because it is paused
+    assert_gm_error(
+        process(
+            &mut context,
+            &[initialize_config(
+                &admin.pubkey(),
+                ConfigSettings::default(),
+            )],
+            &[&admin],
+        )
+        .await,
+        GmError::AlreadyInitialized,
+    );
+
+    process(
+        &mut context,
+        &[set_paused_instructions(&new_admin.pubkey(), 0)],
+        &[&new_admin],
+    )
+    .await
+    .unwrap();
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::*;
+use gm_program::{
+    error::GmError,
+    instruction::GmInstruction,
+    state::{find_profile_address, Profile, ProgramAccount},
+};
+use solana_program_test::tokio;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    signature::Signer,
+};
+
+fn update_profile(wallet: &Pubkey, display_name: &str) -> Instruction {
+    let (profile_address, _) = find_profile_address(&program_id(), wallet);
+    instruction(
+        GmInstruction::UpdateProfile {
+            display_name: display_name.to_string(),
+            avatar: None,
+            bio: String::new(),
+            utc_offset_minutes: 0,
+        },
+        vec![
+            AccountMeta::new_readonly(*wallet, true),
+            AccountMeta::new(profile_address, false),
+        ],
+    )
+}
+
+#[tokio::test]
+async fn update_profile_sets_and_clears_the_display_name() {
+    let mut program_test = program_test();
+    let wallet = add_wallet(&mut program_test);
+    let (profile_address, _) = find_profile_address(&program_id(), &wallet.pubkey());
+    add_state(
+        &mut program_test,
+        profile_address,
+        &Profile::new(wallet.pubkey()),
+        Profile::LEN,
+    );
+    let mut context = program_test.start_with_context().await;
+
+    process(
+        &mut context,
+        &[update_profile(&wallet.pubkey(), "gm 👋")],
+        &[&wallet],
+    )
+    .await
+    .unwrap();
+    let profile: Profile = get_state(&mut context, profile_address).await.unwrap();
+    assert_eq!(profile.display_name, "gm 👋");
+
+    process(
+        &mut context,
+        &[update_profile(&wallet.pubkey(), "")],
+        &[&wallet],
+    )
+    .await
+    .unwrap();
+    let profile: Profile = get_state(&mut context, profile_address).await.unwrap();
+    assert_eq!(profile.display_name, "");
+
+    // A display name that is set still follows the rules
+    assert_gm_error(
+        process(
+            &mut context,
+            &[update_profile(&wallet.pubkey(), "gm\nworld")],
+            &[&wallet],
+        )
+        .await,
+        GmError::NameHasControlCharacter,
+    );
+}
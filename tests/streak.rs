@@ -0,0 +1,101 @@
+mod common;
+
+use common::*;
+use gm_program::state::{find_profile_address, GmStreak, Profile};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+
+async fn get_profile(context: &mut ProgramTestContext, wallet: &Pubkey) -> Profile {
+    let (profile_address, _) = find_profile_address(&program_id(), wallet);
+    get_state(context, profile_address).await.unwrap()
+}
+
+async fn local_day(context: &mut ProgramTestContext, wallet: &Keypair) -> i64 {
+    let profile = get_profile(context, &wallet.pubkey()).await;
+    profile.local_day(get_clock(context).await.unix_timestamp)
+}
+
+#[tokio::test]
+async fn streak_counts_days_as_the_clock_is_warped() {
+    let mut program_test = program_test();
+    let greeter = add_wallet(&mut program_test);
+    let greeting = add_greeting(&mut program_test, &greeter.pubkey(), "gm");
+    add_greeter_accounts(&mut program_test, &greeting, &greeter.pubkey());
+    let mut context = program_test.start_with_context().await;
+
+    // Once warped, the banks server only accepts blockhashes from before the first warp,
+    // one per transaction so that repeated GMs are not taken for duplicates
+    let mut blockhashes = Vec::new();
+    for _ in 0..3 {
+        blockhashes.push(new_blockhash(&mut context).await);
+    }
+
+    // Past the cooldown of the greeter record added at slot zero
+    let mut slot = 1_000;
+    context.warp_to_slot(slot).unwrap();
+    let first_day = local_day(&mut context, &greeter).await;
+    process_with_blockhash(
+        &mut context,
+        &[say_gm(&greeting, &greeter.pubkey())],
+        &[&greeter],
+        blockhashes[0],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        get_profile(&mut context, &greeter.pubkey()).await.streak,
+        GmStreak {
+            current: 1,
+            longest: 1,
+            last_day: first_day,
+        }
+    );
+
+    // A second GM once the cooldown has passed, seconds later on the same day
+    slot += 200;
+    context.warp_to_slot(slot).unwrap();
+    assert_eq!(local_day(&mut context, &greeter).await, first_day);
+    process_with_blockhash(
+        &mut context,
+        &[say_gm(&greeting, &greeter.pubkey())],
+        &[&greeter],
+        blockhashes[1],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        get_profile(&mut context, &greeter.pubkey()).await.streak,
+        GmStreak {
+            current: 1,
+            longest: 1,
+            last_day: first_day,
+        }
+    );
+
+    // Warped clocks follow the estimate of the bank, so warp until the next day starts
+    while local_day(&mut context, &greeter).await == first_day {
+        slot += 10_000;
+        assert!(slot <= 1_000_000, "clock never reached the next day");
+        context.warp_to_slot(slot).unwrap();
+    }
+    assert_eq!(local_day(&mut context, &greeter).await, first_day + 1);
+    process_with_blockhash(
+        &mut context,
+        &[say_gm(&greeting, &greeter.pubkey())],
+        &[&greeter],
+        blockhashes[2],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        get_profile(&mut context, &greeter.pubkey()).await.streak,
+        GmStreak {
+            current: 2,
+            longest: 2,
+            last_day: first_day + 1,
+        }
+    );
+}
@@ -0,0 +1,224 @@
+mod common;
+
+use common::*;
+use gm_program::{
+    error::GmError,
+    instruction::GmInstruction,
+    state::{find_config_address, Config, ConfigSettings},
+};
+use solana_program_test::{tokio, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+
+const TIMELOCK_SLOTS: u64 = 100;
+
+/// Default settings, except that changes wait for `TIMELOCK_SLOTS`
+fn timelocked_settings() -> ConfigSettings {
+    ConfigSettings {
+        timelock_slots: TIMELOCK_SLOTS,
+        ..ConfigSettings::default()
+    }
+}
+
+/// Add a config with timelocked settings, administered by a new wallet
+fn add_timelocked_config(program_test: &mut ProgramTest) -> Keypair {
+    let admin = add_wallet(program_test);
+    let config = Config {
+        admin: admin.pubkey(),
+        settings: timelocked_settings(),
+        ..Config::uninitialized()
+    };
+    add_config_state(program_test, &config);
+    admin
+}
+
+fn update_config(admin: &Pubkey, settings: ConfigSettings) -> Instruction {
+    config_instruction(
+        GmInstruction::UpdateConfig { settings },
+        vec![AccountMeta::new_readonly(*admin, true)],
+    )
+}
+
+fn accept_admin(new_admin: &Pubkey) -> Instruction {
+    config_instruction(
+        GmInstruction::AcceptAdmin,
+        vec![AccountMeta::new_readonly(*new_admin, true)],
+    )
+}
+
+async fn get_config(context: &mut ProgramTestContext) -> Config {
+    let (config_address, _) = find_config_address(&program_id());
+    get_state(context, config_address).await.unwrap()
+}
+
+#[tokio::test]
+async fn updated_settings_wait_for_the_timelock() {
+    let mut program_test = program_test();
+    let admin = add_timelocked_config(&mut program_test);
+    let name = "g".repeat(9);
+    let greeting = add_greeting(&mut program_test, &admin.pubkey(), &name);
+    let mut context = program_test.start_with_context().await;
+
+    let settings = ConfigSettings {
+        max_name_len: 8,
+        ..timelocked_settings()
+    };
+    let slot = get_clock(&mut context).await.slot;
+    process(
+        &mut context,
+        &[update_config(&admin.pubkey(), settings)],
+        &[&admin],
+    )
+    .await
+    .unwrap();
+    let config = get_config(&mut context).await;
+    assert_eq!(config.settings, timelocked_settings());
+    let pending = config.pending_settings.unwrap();
+    assert_eq!(pending.settings, settings);
+    assert!(pending.effective_slot >= slot + TIMELOCK_SLOTS);
+
+    // The name is within the settings in effect until the timelock has passed
+    let blockhashes = [
+        new_blockhash(&mut context).await,
+        new_blockhash(&mut context).await,
+    ];
+    let rename = update(&greeting, &admin.pubkey(), &name.to_uppercase());
+    process_with_blockhash(
+        &mut context,
+        std::slice::from_ref(&rename),
+        &[&admin],
+        blockhashes[0],
+    )
+    .await
+    .unwrap();
+
+    // Past the timelock the settings apply without the config being written again
+    context.warp_to_slot(pending.effective_slot + 1).unwrap();
+    assert_gm_error(
+        process_with_blockhash(&mut context, &[rename], &[&admin], blockhashes[1]).await,
+        GmError::NameTooLong,
+    );
+    assert_eq!(
+        get_config(&mut context).await.pending_settings,
+        Some(pending)
+    );
+}
+
+#[tokio::test]
+async fn accept_admin_waits_for_the_timelock() {
+    let mut program_test = program_test();
+    let admin = add_timelocked_config(&mut program_test);
+    let new_admin = add_wallet(&mut program_test);
+    let other = add_wallet(&mut program_test);
+    let mut context = program_test.start_with_context().await;
+
+    process(
+        &mut context,
+        &[config_instruction(
+            GmInstruction::ProposeAdmin {
+                new_admin: new_admin.pubkey(),
+            },
+            vec![AccountMeta::new_readonly(admin.pubkey(), true)],
+        )],
+        &[&admin],
+    )
+    .await
+    .unwrap();
+    let pending = get_config(&mut context).await.pending_admin.unwrap();
+    assert_eq!(pending.admin, new_admin.pubkey());
+
+    let blockhashes = [
+        new_blockhash(&mut context).await,
+        new_blockhash(&mut context).await,
+    ];
+    assert_gm_error(
+        process_with_blockhash(
+            &mut context,
+            &[accept_admin(&new_admin.pubkey())],
+            &[&new_admin],
+            blockhashes[0],
+        )
+        .await,
+        GmError::TimelockNotElapsed,
+    );
+
+    context.warp_to_slot(pending.effective_slot + 1).unwrap();
+    assert_gm_error(
+        process_with_blockhash(
+            &mut context,
+            &[accept_admin(&other.pubkey())],
+            &[&other],
+            blockhashes[0],
+        )
+        .await,
+        GmError::NoAdminProposal,
+    );
+    process_with_blockhash(
+        &mut context,
+        &[accept_admin(&new_admin.pubkey())],
+        &[&new_admin],
+        blockhashes[1],
+    )
+    .await
+    .unwrap();
+
+    let config = get_config(&mut context).await;
+    assert_eq!(config.admin, new_admin.pubkey());
+    assert_eq!(config.pending_admin, None);
+}
+
+#[tokio::test]
+async fn cancel_proposals_clears_every_pending_change() {
+    let mut program_test = program_test();
+    let admin = add_timelocked_config(&mut program_test);
+    let mut context = program_test.start_with_context().await;
+
+    let admin_only = |gm_instruction| {
+        config_instruction(
+            gm_instruction,
+            vec![AccountMeta::new_readonly(admin.pubkey(), true)],
+        )
+    };
+    process(
+        &mut context,
+        &[
+            admin_only(GmInstruction::UpdateConfig {
+                settings: ConfigSettings {
+                    max_name_len: 8,
+                    ..timelocked_settings()
+                },
+            }),
+            admin_only(GmInstruction::ProposeAdmin {
+                new_admin: Pubkey::new_unique(),
+            }),
+            admin_only(GmInstruction::SetFeeExempt {
+                wallet: Pubkey::new_unique(),
+                exempt: true,
+            }),
+        ],
+        &[&admin],
+    )
+    .await
+    .unwrap();
+    let config = get_config(&mut context).await;
+    assert!(config.pending_settings.is_some());
+    assert!(config.pending_admin.is_some());
+    assert!(config.pending_fee_exempt.is_some());
+
+    process(
+        &mut context,
+        &[admin_only(GmInstruction::CancelProposals)],
+        &[&admin],
+    )
+    .await
+    .unwrap();
+    let config = get_config(&mut context).await;
+    assert_eq!(config.pending_settings, None);
+    assert_eq!(config.pending_admin, None);
+    assert_eq!(config.pending_fee_exempt, None);
+    assert_eq!(config.settings, timelocked_settings());
+    assert!(config.fee_exempt.is_empty());
+}
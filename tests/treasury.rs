@@ -0,0 +1,179 @@
+mod common;
+
+use common::*;
+use gm_program::{
+    error::GmError,
+    instruction::GmInstruction,
+    state::{find_treasury_address, Config, ConfigSettings, FeeSchedule},
+};
+use solana_program_test::{tokio, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction,
+};
+
+/// Fee of `SayGm` in the configs of these tests
+const SAY_GM_LAMPORTS: u64 = 5_000;
+
+/// Add a config charging `SAY_GM_LAMPORTS` for `SayGm`, administered by a new wallet
+fn add_fee_config(program_test: &mut ProgramTest, fee_exempt: Vec<Pubkey>) -> Keypair {
+    let admin = add_wallet(program_test);
+    let config = Config {
+        admin: admin.pubkey(),
+        settings: ConfigSettings {
+            fees: FeeSchedule {
+                say_gm_lamports: SAY_GM_LAMPORTS,
+                ..FeeSchedule::default()
+            },
+            ..ConfigSettings::default()
+        },
+        fee_exempt,
+        ..Config::uninitialized()
+    };
+    add_config_state(program_test, &config);
+    admin
+}
+
+fn withdraw_treasury(admin: &Pubkey, destination: &Pubkey, lamports: u64) -> Instruction {
+    let (treasury_address, _) = find_treasury_address(&program_id());
+    instruction(
+        GmInstruction::WithdrawTreasury { lamports },
+        vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(treasury_address, false),
+            AccountMeta::new(*destination, false),
+        ],
+    )
+}
+
+async fn get_lamports(context: &mut ProgramTestContext, address: Pubkey) -> u64 {
+    context.banks_client.get_balance(address).await.unwrap()
+}
+
+/// Say GM once the cooldown of the greeter record added at slot zero has passed, returning
+/// the lamports the treasury gained
+async fn say_gm_past_the_cooldown(
+    context: &mut ProgramTestContext,
+    greeting: &Pubkey,
+    greeter: &Keypair,
+) -> u64 {
+    let (treasury_address, _) = find_treasury_address(&program_id());
+    let before = get_lamports(context, treasury_address).await;
+
+    let blockhash = new_blockhash(context).await;
+    context.warp_to_slot(1_000).unwrap();
+    process_with_blockhash(
+        context,
+        &[say_gm(greeting, &greeter.pubkey())],
+        &[greeter],
+        blockhash,
+    )
+    .await
+    .unwrap();
+
+    get_lamports(context, treasury_address).await - before
+}
+
+#[tokio::test]
+async fn say_gm_pays_the_fee_to_the_treasury() {
+    let mut program_test = program_test();
+    add_fee_config(&mut program_test, vec![]);
+    let greeter = add_wallet(&mut program_test);
+    let greeting = add_greeting(&mut program_test, &greeter.pubkey(), "gm");
+    add_greeter_accounts(&mut program_test, &greeting, &greeter.pubkey());
+    let mut context = program_test.start_with_context().await;
+
+    assert_eq!(
+        say_gm_past_the_cooldown(&mut context, &greeting, &greeter).await,
+        SAY_GM_LAMPORTS
+    );
+}
+
+#[tokio::test]
+async fn fee_exempt_wallet_pays_no_fee() {
+    let mut program_test = program_test();
+    let greeter = add_wallet(&mut program_test);
+    add_fee_config(&mut program_test, vec![greeter.pubkey()]);
+    let greeting = add_greeting(&mut program_test, &greeter.pubkey(), "gm");
+    add_greeter_accounts(&mut program_test, &greeting, &greeter.pubkey());
+    let mut context = program_test.start_with_context().await;
+
+    assert_eq!(
+        say_gm_past_the_cooldown(&mut context, &greeting, &greeter).await,
+        0
+    );
+}
+
+#[tokio::test]
+async fn withdraw_treasury_keeps_its_rent() {
+    let mut program_test = program_test();
+    let admin = add_fee_config(&mut program_test, vec![]);
+    let payer = add_wallet(&mut program_test);
+    let destination = Pubkey::new_unique();
+    let mut context = program_test.start_with_context().await;
+
+    let (treasury_address, _) = find_treasury_address(&program_id());
+    process(
+        &mut context,
+        &[system_instruction::transfer(
+            &payer.pubkey(),
+            &treasury_address,
+            SAY_GM_LAMPORTS,
+        )],
+        &[&payer],
+    )
+    .await
+    .unwrap();
+    let rent = get_lamports(&mut context, treasury_address).await - SAY_GM_LAMPORTS;
+
+    assert_gm_error(
+        process(
+            &mut context,
+            &[withdraw_treasury(
+                &admin.pubkey(),
+                &destination,
+                SAY_GM_LAMPORTS + 1,
+            )],
+            &[&admin],
+        )
+        .await,
+        GmError::InsufficientFunds,
+    );
+
+    process(
+        &mut context,
+        &[withdraw_treasury(
+            &admin.pubkey(),
+            &destination,
+            SAY_GM_LAMPORTS,
+        )],
+        &[&admin],
+    )
+    .await
+    .unwrap();
+    assert_eq!(get_lamports(&mut context, treasury_address).await, rent);
+    assert_eq!(
+        get_lamports(&mut context, destination).await,
+        SAY_GM_LAMPORTS
+    );
+}
+
+#[tokio::test]
+async fn withdraw_treasury_by_another_wallet_fails() {
+    let mut program_test = program_test();
+    add_fee_config(&mut program_test, vec![]);
+    let other = add_wallet(&mut program_test);
+    let mut context = program_test.start_with_context().await;
+
+    assert_gm_error(
+        process(
+            &mut context,
+            &[withdraw_treasury(&other.pubkey(), &other.pubkey(), 0)],
+            &[&other],
+        )
+        .await,
+        GmError::Unauthorized,
+    );
+}